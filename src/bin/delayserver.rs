@@ -22,8 +22,15 @@ async fn main() -> std::io::Result<()> {
         .nth(1)
         .unwrap_or_else(|| String::from("localhost"));
 
-    HttpServer::new(|| App::new().service(delay))
-        .bind((url, 7070))?
-        .run()
-        .await
+    let server = HttpServer::new(|| App::new().service(delay));
+    // A path (anything with a `/` in it, which a host name can't contain)
+    // means we listen on a Unix socket there instead of on port 7070.
+    let server = if url.contains('/') {
+        // Binding fails if the socket is still there from an earlier run.
+        let _ = std::fs::remove_file(&url);
+        server.bind_uds(url)?
+    } else {
+        server.bind((url, 7070))?
+    };
+    server.run().await
 }
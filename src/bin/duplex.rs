@@ -0,0 +1,149 @@
+//
+// Two tasks sharing one `TcpStream`: `Reader` waits for data to arrive while
+// `Writer` sends a message every now and then. The reactor keeps a Waker for
+// each direction, so the two tasks don't replace each other's Wakers, and
+// each event only wakes the task it concerns.
+//
+// The other end of the connection is an echo server that the example starts
+// on a thread of its own.
+//
+use std::{
+    cell::RefCell,
+    io::{Read, Write},
+    net::TcpListener,
+    rc::Rc,
+    thread,
+    time::Duration,
+};
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+    io::PollEvented,
+    reactor::Direction,
+    runtime_two,
+    time::{self, Sleep},
+};
+use mio::{Interest, net::TcpStream};
+
+const MESSAGES: [&str; 3] = ["Hello", "from the", "writer"];
+
+type Shared = Rc<RefCell<PollEvented<TcpStream>>>;
+
+fn main() {
+    let addr = start_echo_server();
+    let mut executor = runtime_two::init();
+    executor.block_on(AsyncMain {
+        addr: addr.to_string(),
+    });
+}
+
+/// Echoes everything back, one connection at a time.
+fn start_echo_server() -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut buf = [0; 1024];
+            while let Ok(n @ 1..) = stream.read(&mut buf) {
+                stream.write_all(&buf[..n]).unwrap();
+            }
+        }
+    });
+    addr
+}
+
+struct AsyncMain {
+    addr: String,
+}
+
+impl Future for AsyncMain {
+    type Output = String;
+
+    fn poll(&mut self, _waker: &Waker) -> PollState<Self::Output> {
+        let stream = TcpStream::connect(self.addr.parse().unwrap()).unwrap();
+        let interest = Interest::READABLE | Interest::WRITABLE;
+        let stream =
+            Rc::new(RefCell::new(PollEvented::new(stream, interest).unwrap()));
+        let expected = MESSAGES.iter().map(|m| m.len()).sum();
+        executor::spawn(Reader {
+            stream: stream.clone(),
+            remaining: expected,
+        });
+        executor::spawn(Writer {
+            stream,
+            next: 0,
+            sleep: None,
+        });
+        PollState::Ready(String::new())
+    }
+}
+
+/// Reads until `remaining` bytes have been echoed back.
+struct Reader {
+    stream: Shared,
+    remaining: usize,
+}
+
+impl Future for Reader {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        let mut buf = [0; 1024];
+        while self.remaining > 0 {
+            let mut stream = self.stream.borrow_mut();
+            match stream.poll_io(Direction::Read, waker, |s| s.read(&mut buf)) {
+                PollState::Ready(Ok(0)) => panic!("Connection closed early"),
+                PollState::Ready(Ok(n)) => {
+                    let text = String::from_utf8_lossy(&buf[..n]);
+                    println!("Reader: received {text:?}");
+                    self.remaining = self.remaining.saturating_sub(n);
+                }
+                PollState::Ready(Err(e)) => panic!("Error reading: {e}"),
+                PollState::NotReady => {
+                    println!("Reader: waiting for data");
+                    return PollState::NotReady;
+                }
+            }
+        }
+        println!("Reader: done");
+        PollState::Ready(String::new())
+    }
+}
+
+/// Sends each of `MESSAGES`, waiting a bit before each of them.
+struct Writer {
+    stream: Shared,
+    next: usize,
+    sleep: Option<Sleep>,
+}
+
+impl Future for Writer {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        while let Some(msg) = MESSAGES.get(self.next) {
+            let sleep = self.sleep.get_or_insert_with(|| {
+                time::try_sleep(Duration::from_millis(300))
+            });
+            if let PollState::NotReady = sleep.poll(waker) {
+                return PollState::NotReady;
+            }
+            let mut stream = self.stream.borrow_mut();
+            let written = stream
+                .poll_io(Direction::Write, waker, |s| s.write(msg.as_bytes()));
+            match written {
+                PollState::Ready(Ok(n)) => {
+                    assert_eq!(n, msg.len(), "Short write");
+                    println!("Writer: sent {msg:?}");
+                    self.next += 1;
+                    self.sleep = None;
+                }
+                PollState::Ready(Err(e)) => panic!("Error writing: {e}"),
+                PollState::NotReady => return PollState::NotReady,
+            }
+        }
+        PollState::Ready(String::new())
+    }
+}
@@ -0,0 +1,116 @@
+//
+// Shows what happens to requests that fail: instead of panicking (and taking
+// the executor down with them), they resolve to an `HttpError`.
+//
+// * `refused`: nothing is listening on the port, so the connection is
+//   refused.
+// * `reset`: the server accepts the connection, and then closes it without
+//   reading the request, which makes the OS reset the connection.
+// * `garbage`: the server answers with something that isn't HTTP, so the
+//   response can't be parsed.
+// * `ok`: the server answers, to show that the other requests didn't get in
+//   the way.
+//
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener},
+    thread,
+};
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+    http_waker::HttpGetFuture,
+    runtime_two,
+};
+
+fn main() {
+    let refused = {
+        // Bind to a free port and close it again straight away, so that we
+        // know nobody is listening there.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+    let reset = start_server(|stream| {
+        // Give the request time to arrive, so that it's sitting unread in
+        // our receive buffer when we close the socket.
+        thread::sleep(std::time::Duration::from_millis(50));
+        drop(stream);
+    });
+    let garbage = start_server(|stream| {
+        respond(stream, b"SSH-2.0-OpenSSH_9.6\r\n\r\n");
+    });
+    let ok = start_server(|stream| {
+        respond(stream, b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nOK");
+    });
+
+    let mut executor = runtime_two::init();
+    executor.block_on(AsyncMain(vec![
+        ("refused", refused),
+        ("reset", reset),
+        ("garbage", garbage),
+        ("ok", ok),
+    ]));
+}
+
+/// Starts a server on a free port that hands every connection to `handle`.
+fn start_server(
+    handle: impl Fn(std::net::TcpStream) + Send + 'static,
+) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            handle(stream.unwrap());
+        }
+    });
+    addr
+}
+
+/// Reads the request and answers it with `response`.
+fn respond(mut stream: std::net::TcpStream, response: &[u8]) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    while reader.read_line(&mut line).unwrap_or(0) > 2 {
+        line.clear();
+    }
+    stream.write_all(response).unwrap();
+}
+
+struct AsyncMain(Vec<(&'static str, SocketAddr)>);
+
+impl Future for AsyncMain {
+    type Output = String;
+
+    fn poll(&mut self, _waker: &Waker) -> PollState<Self::Output> {
+        for (name, addr) in self.0.drain(..) {
+            let request = HttpGetFuture::with_addr(addr, "/");
+            executor::spawn(Report { name, request });
+        }
+        PollState::Ready(String::new())
+    }
+}
+
+/// Prints how the request it wraps turned out.
+struct Report {
+    name: &'static str,
+    request: HttpGetFuture,
+}
+
+impl Future for Report {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        match self.request.poll(waker) {
+            PollState::Ready(Ok(response)) => {
+                println!("{}: response:\n{response}\n", self.name);
+                PollState::Ready(String::new())
+            }
+            PollState::Ready(Err(e)) => {
+                println!("{}: error: {e} ({e:?})\n", self.name);
+                PollState::Ready(String::new())
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//
+// Shows how the client finds the end of a response without waiting for the
+// server to close the connection. The local server below answers every
+// request on its own connection, and keeps it open for `HOLD` afterwards, so
+// the requests that finish straight away are the ones whose response says
+// where it ends:
+//
+// * `/length`: a body with a `Content-Length`.
+// * `/chunked`: a `Transfer-Encoding: chunked` body, sent a chunk at a time,
+//   with a trailer after the last chunk.
+// * HEAD `/length`: the head only, even though it has a `Content-Length`.
+// * `/no-content` and `/not-modified`: a 204 and a 304, which never have a
+//   body.
+// * `/until-close`: no length at all, so the body ends when the server
+//   closes the connection, which it does after `HOLD`.
+// * `/truncated`: the server closes the connection halfway through the
+//   body, so the response is incomplete.
+//
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+    http_waker::HttpGetFuture,
+    runtime_two,
+};
+
+// How long the server keeps a connection open after it has answered.
+const HOLD: Duration = Duration::from_secs(2);
+
+fn main() {
+    let addr = start_server();
+    let get = |path| ("GET", HttpGetFuture::with_addr(addr, path));
+    let requests = vec![
+        get("/length"),
+        get("/chunked"),
+        (
+            "HEAD",
+            HttpGetFuture::head(&format!("http://{addr}/length")),
+        ),
+        get("/no-content"),
+        get("/not-modified"),
+        get("/until-close"),
+        get("/truncated"),
+    ];
+
+    let start = Instant::now();
+    let mut executor = runtime_two::init();
+    executor.block_on(AsyncMain(requests));
+    println!("ELAPSED TIME: {}", start.elapsed().as_secs_f32());
+}
+
+/// Starts a server on a free port that answers every request on a thread of
+/// its own.
+fn start_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            thread::spawn(move || respond(stream));
+        }
+    });
+    addr
+}
+
+fn respond(mut stream: TcpStream) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let mut line = String::new();
+    while reader.read_line(&mut line).unwrap_or(0) > 2 {
+        line.clear();
+    }
+
+    let path = request_line.split(' ').nth(1).unwrap_or_default();
+    let mut send = |data: &str| stream.write_all(data.as_bytes()).unwrap();
+    match path {
+        "/length" => {
+            send("HTTP/1.1 200 OK\r\ncontent-length: 12\r\n\r\nHello world!")
+        }
+        "/chunked" => {
+            send("HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n");
+            for chunk in ["Hello", " chunked", " world!"] {
+                thread::sleep(Duration::from_millis(100));
+                send(&format!("{:x}\r\n{chunk}\r\n", chunk.len()));
+            }
+            send("0\r\nx-checksum: 1234\r\n\r\n");
+        }
+        "/no-content" => send("HTTP/1.1 204 No Content\r\n\r\n"),
+        "/not-modified" => {
+            send("HTTP/1.1 304 Not Modified\r\ncontent-length: 12\r\n\r\n")
+        }
+        "/until-close" => send("HTTP/1.1 200 OK\r\n\r\nUntil close"),
+        "/truncated" => {
+            send("HTTP/1.1 200 OK\r\ncontent-length: 100\r\n\r\nOnly half");
+            return;
+        }
+        _ => send("HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n"),
+    }
+    thread::sleep(HOLD);
+}
+
+struct AsyncMain(Vec<(&'static str, HttpGetFuture)>);
+
+impl Future for AsyncMain {
+    type Output = String;
+
+    fn poll(&mut self, _waker: &Waker) -> PollState<Self::Output> {
+        for (method, request) in self.0.drain(..) {
+            executor::spawn(Report {
+                method,
+                start: Instant::now(),
+                request,
+            });
+        }
+        PollState::Ready(String::new())
+    }
+}
+
+/// Prints how the request it wraps turned out, and how long it took.
+struct Report {
+    method: &'static str,
+    start: Instant,
+    request: HttpGetFuture,
+}
+
+impl Future for Report {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        let result = match self.request.poll(waker) {
+            PollState::Ready(result) => result,
+            PollState::NotReady => return PollState::NotReady,
+        };
+        let request = format!("{} {}", self.method, self.request.path);
+        let elapsed = self.start.elapsed().as_secs_f32();
+        match result {
+            Ok(response) => println!(
+                "{request} after {elapsed:.2}s: {} {}, body {:?}, \
+                 trailers {:?}",
+                response.status,
+                response.reason,
+                response.text(),
+                response.trailers,
+            ),
+            Err(e) => println!("{request} after {elapsed:.2}s: error: {e}"),
+        }
+        PollState::Ready(String::new())
+    }
+}
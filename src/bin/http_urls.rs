@@ -0,0 +1,110 @@
+//
+// Sends requests to full URLs instead of just paths: one that goes through
+// name resolution, one to an IPv6 literal, and a few that fail before a
+// connection is ever made. The local server echoes the `Host` header it
+// received, so we can see that it's right.
+//
+// The `localhost` request goes to the delayserver, so start it first:
+//
+//     cargo run --bin delayserver
+//
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener},
+    thread,
+};
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+    http_waker::{Http, HttpGetFuture},
+    runtime_two,
+};
+
+fn main() {
+    let echo = start_echo_server("[::1]:0".parse().unwrap());
+    let urls = vec![
+        "/200/BarePath".to_string(),
+        "http://localhost:7070/200/Resolved".to_string(),
+        format!("http://{echo}/ipv6?query=1#fragment"),
+        "ftp://localhost/".to_string(),
+        "http://[::1/".to_string(),
+        "http://localhost:99999/".to_string(),
+        "http://does-not-exist.invalid/".to_string(),
+    ];
+
+    let mut executor = runtime_two::init();
+    executor.block_on(AsyncMain(urls));
+}
+
+/// Starts a server that answers every request with the `Host` header and
+/// the request line it got.
+fn start_echo_server(addr: SocketAddr) -> SocketAddr {
+    let listener = TcpListener::bind(addr).unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            let head: Vec<String> = reader
+                .lines()
+                .map_while(Result::ok)
+                .take_while(|line| !line.is_empty())
+                .collect();
+            let host = head.iter().find(|l| l.starts_with("Host:"));
+            let body = format!("{}\n{}", head[0], host.unwrap());
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    addr
+}
+
+struct AsyncMain(Vec<String>);
+
+impl Future for AsyncMain {
+    type Output = String;
+
+    fn poll(&mut self, _waker: &Waker) -> PollState<Self::Output> {
+        for url in self.0.drain(..) {
+            let request = Http::try_get(url.clone());
+            executor::spawn(Report { url, request });
+        }
+        PollState::Ready(String::new())
+    }
+}
+
+/// Prints how the request it wraps turned out.
+struct Report {
+    url: String,
+    request: HttpGetFuture,
+}
+
+impl Future for Report {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        match self.request.poll(waker) {
+            PollState::Ready(Ok(response)) => {
+                println!(
+                    "{}: {} {} ({} bytes, content-type: {}):\n{}\n",
+                    self.url,
+                    response.status,
+                    response.reason,
+                    response.body.len(),
+                    response.header("content-type").unwrap_or("none"),
+                    response.text()
+                );
+                PollState::Ready(String::new())
+            }
+            PollState::Ready(Err(e)) => {
+                println!("{}: error: {e}\n", self.url);
+                PollState::Ready(String::new())
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
@@ -77,8 +77,9 @@ impl Future for Coroutine0 {
     type Output = String;
 
     // Supress warnings about unused variables since `waker` may not always
-    // be used directly.
-    #[allow(unused)]
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
     fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
         loop {
             match self.state {
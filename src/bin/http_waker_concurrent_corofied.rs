@@ -3,21 +3,50 @@
 // in order to generate the state machine transformation for the async code.
 //
 
+use std::env;
 use std::time::Instant;
 use chrono::Local;
 
 use learn_async_rust::{
     executor::{self, Waker},
     future_with_waker::{Future, PollState},
-    http_waker::Http, runtime_two,
+    http_pool, http_waker::Http, runtime_two, trace,
 };
 
+// Run with `TRACE_FILE=trace.json` to record a timeline of the tasks that
+// can be opened in Perfetto (https://ui.perfetto.dev).
+//
+// Every task sends a second request once it has the response to its first,
+// which goes out over the same connection. Run with `NO_POOL=1` to open a
+// new connection for every request instead, or with `MAX_PER_HOST=n` to let
+// only `n` requests at a time through to the delayserver.
 fn main() {
+    let trace_file = env::var("TRACE_FILE").ok();
+    if trace_file.is_some() {
+        trace::enable();
+    }
+    if env::var_os("NO_POOL").is_some() {
+        http_pool::set_max_idle(0);
+    }
+    if let Ok(max) = env::var("MAX_PER_HOST") {
+        http_pool::set_max_per_host(max.parse().unwrap());
+    }
+
     let start = Instant::now();
     let mut executor = runtime_two::init();
     executor.block_on(async_main());
 
     println!("\nELAPSED TIME: {}", start.elapsed().as_secs_f32());
+    let stats = http_pool::stats();
+    println!(
+        "CONNECTIONS: {} opened, {} reused",
+        stats.opened, stats.reused
+    );
+
+    if let Some(path) = trace_file {
+        trace::write(&path).unwrap();
+        println!("Trace written to {path}");
+    }
 }
 
 
@@ -35,6 +64,10 @@ fn main() {
 //     let now = Local::now();
 //     println!("{now} [{}] Response:\n{txt}", std::thread::current().name().unwrap());
 //     println!();
+//     let txt = Http::get("/0/HelloAgain".to_string()).wait;
+//     let now = Local::now();
+//     println!("{now} [{}] Response:\n{txt}", std::thread::current().name().unwrap());
+//     println!();
 
 // }
 
@@ -49,6 +82,7 @@ fn request(i: usize) -> impl Future<Output=String> {
 enum State0 {
     Start(usize),
     Wait1(Box<dyn Future<Output = String>>),
+    Wait2(Box<dyn Future<Output = String>>),
     Resolved,
 }
 
@@ -67,8 +101,9 @@ impl Future for Coroutine0 {
     type Output = String;
 
     // Supress warnings about unused variables since `waker` may not always
-    // be used directly.
-    #[allow(unused)]
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
     fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
         loop {
         match self.state {
@@ -87,6 +122,22 @@ impl Future for Coroutine0 {
                             // ---- Code you actually wrote ----
                             let now = Local::now();
     println!("{now} [{}] Response:\n{txt}", std::thread::current().name().unwrap());
+    println!();
+
+                            // ---------------------------------
+                            let fut2 = Box::new( Http::get("/0/HelloAgain".to_string()));
+                            self.state = State0::Wait2(fut2);
+                        }
+                        PollState::NotReady => break PollState::NotReady,
+                    }
+                }
+
+                State0::Wait2(ref mut f2) => {
+                    match f2.poll(waker) {
+                        PollState::Ready(txt) => {
+                            // ---- Code you actually wrote ----
+                            let now = Local::now();
+    println!("{now} [{}] Response:\n{txt}", std::thread::current().name().unwrap());
     println!();
 
                             // ---------------------------------
@@ -146,8 +197,9 @@ impl Future for Coroutine1 {
     type Output = String;
 
     // Supress warnings about unused variables since `waker` may not always
-    // be used directly.
-    #[allow(unused)]
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
     fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
         loop {
         match self.state {
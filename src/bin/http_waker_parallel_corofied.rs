@@ -5,6 +5,7 @@
 
 use std::time::Instant;
 use chrono::Local;
+use std::sync::mpsc;
 use std::thread::Builder;
 
 use learn_async_rust::{
@@ -17,24 +18,43 @@ use learn_async_rust::{
 // To get the number of cores on a Mac:
 // `sysctl -n hw.ncpu`
 //
-// This will send 60 HTTP GET requests in total.
+// This will send 65 HTTP GET requests in total.
 //
 fn main() {
     let start = Instant::now();
-    let mut executor = runtime_two::init();
+    // One reactor shard per executor, so that each executor gets an event
+    // loop thread of its own.
+    let mut executor = runtime_two::Builder::new().reactor_shards(12).build();
     let mut handles = vec![];
+    let (tx, rx) = mpsc::channel();
 
     // Create 11 parallel executors (each in their own thread) each running 
-    // 5 tasks.
+    // 5 tasks. Each executor sends us a `Handle` so that we can give it more
+    // work from this thread.
     for i in 1..12 {
         let name = format!("exec-{i}");
+        let tx = tx.clone();
         let h = Builder::new().name(name).spawn(move || {
             let mut executor = Executor::new();
+            tx.send((i, executor::handle())).unwrap();
+            drop(tx);
             executor.block_on(async_main());
         }).unwrap();
         handles.push(h);
     }
-  
+    drop(tx);
+
+    // Keep the handle to `exec-3` and drop all the others, so that those
+    // executors can finish as soon as their own tasks are done.
+    let exec_3 = rx.iter().fold(None, |found, (i, h)| found.or((i == 3).then_some(h))).unwrap();
+
+    // Push another 5 tasks from the `main` thread onto `exec-3`.
+    for i in 0..5 {
+        let path = format!("/{}/FromMain-{i}", i * 100);
+        exec_3.spawn(Print(Http::get(path)));
+    }
+    drop(exec_3);
+
     // Submit another 5 tasks on the `main` thread.
     executor.block_on(async_main());
     handles.into_iter().for_each(|h| h.join().unwrap());
@@ -42,6 +62,27 @@ fn main() {
     println!("\nELAPSED TIME: {}", start.elapsed().as_secs_f32());
 }
 
+// Prints the response of the future it wraps. The coroutines `corofy_waker`
+// generates keep their futures in a `Box<dyn Future>`, which isn't `Send`,
+// so we write this one by hand to be able to send it to another executor.
+struct Print<F>(F);
+
+impl<F: Future<Output = String>> Future for Print<F> {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        match self.0.poll(waker) {
+            PollState::Ready(txt) => {
+                let now = Local::now();
+                println!("{now} [{}] Response:\n{txt}", std::thread::current().name().unwrap());
+                println!();
+                PollState::Ready(String::new())
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
 
 
 
@@ -89,8 +130,9 @@ impl Future for Coroutine0 {
     type Output = String;
 
     // Supress warnings about unused variables since `waker` may not always
-    // be used directly.
-    #[allow(unused)]
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
     fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
         loop {
         match self.state {
@@ -168,8 +210,9 @@ impl Future for Coroutine1 {
     type Output = String;
 
     // Supress warnings about unused variables since `waker` may not always
-    // be used directly.
-    #[allow(unused)]
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
     fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
         loop {
         match self.state {
@@ -0,0 +1,329 @@
+//
+// This is the template file that needs to be run through `corofy_waker` 
+// in order to generate the state machine transformation for the async code.
+//
+// Runs shell steps as child processes, concurrently: every step takes a
+// second, but since waiting for a child and reading its output goes through
+// the reactor, they all finish after about a second instead of one after the
+// other. The last step fails, to show what that looks like.
+//
+// `Upcase` is a hand-written future that uses the pipes directly: it writes to
+// the stdin of `tr`, reads what comes back on its stdout, and waits for its
+// exit status.
+//
+
+use std::time::Instant;
+use std::process::Stdio;
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+    io,
+    process::{self, Child, Command},
+    runtime_two,
+};
+
+fn main() {
+    let start = Instant::now();
+    let mut executor = runtime_two::init();
+    executor.block_on(async_main());
+    println!("\nELAPSED TIME: {}", start.elapsed().as_secs_f32());
+}
+
+
+
+
+
+
+
+// Where `Upcase` is.
+enum State {
+    Writing(usize),
+    Reading(usize),
+    Waiting,
+}
+
+struct Upcase {
+    child: Child,
+    input: &'static str,
+    output: Vec<u8>,
+    state: State,
+}
+
+impl Upcase {
+    fn new(input: &'static str) -> Self {
+        let child = Command::new("tr")
+            .arg("a-z")
+            .arg("A-Z")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        Self { child, input, output: vec![], state: State::Writing(0) }
+    }
+}
+
+impl Future for Upcase {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+            match &mut self.state {
+                State::Writing(written) => {
+                    let stdin = self.child.stdin.as_mut().unwrap();
+                    let input = self.input.as_bytes();
+                    match io::poll_write_all(stdin, waker, input, written) {
+                        PollState::Ready(result) => {
+                            result.unwrap();
+                            // Closing stdin tells `tr` that there's no more
+                            // input coming.
+                            self.child.stdin = None;
+                            self.state = State::Reading(0);
+                        }
+                        PollState::NotReady => return PollState::NotReady,
+                    }
+                }
+                State::Reading(read) => {
+                    let stdout = self.child.stdout.as_mut().unwrap();
+                    let output = &mut self.output;
+                    match io::poll_read_to_end(stdout, waker, output, read) {
+                        PollState::Ready(result) => {
+                            result.unwrap();
+                            self.state = State::Waiting;
+                        }
+                        PollState::NotReady => return PollState::NotReady,
+                    }
+                }
+                State::Waiting => match self.child.poll_wait(waker) {
+                    PollState::Ready(status) => {
+                        let output = String::from_utf8_lossy(&self.output);
+                        print!("tr ({}): {output}", status.unwrap());
+                        return PollState::Ready(String::new());
+                    }
+                    PollState::NotReady => return PollState::NotReady,
+                },
+            }
+        }
+    }
+}
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn step(i: usize) {
+//     let script = format!("sleep 1; echo step {i} ran in process $$");
+//     let txt = process::shell(script).wait;
+//     print!("{txt}");
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn step(i: usize) -> impl Future<Output=String> {
+    Coroutine0::new(i)
+}
+        
+enum State0 {
+    Start(usize),
+    Wait1(Box<dyn Future<Output = String>>),
+    Resolved,
+}
+
+struct Coroutine0 {
+    state: State0,
+}
+
+impl Coroutine0 {
+    fn new(i: usize) -> Self {
+        Self { state: State0::Start(i) }
+    }
+}
+
+
+impl Future for Coroutine0 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State0::Start(i) => {
+                    // ---- Code you actually wrote ----
+                    let script = format!("sleep 1; echo step {i} ran in process $$");
+
+                    // ---------------------------------
+                    let fut1 = Box::new( process::shell(script));
+                    self.state = State0::Wait1(fut1);
+                }
+
+                State0::Wait1(ref mut f1) => {
+                    match f1.poll(waker) {
+                        PollState::Ready(txt) => {
+                            // ---- Code you actually wrote ----
+                            print!("{txt}");
+
+                            // ---------------------------------
+                            self.state = State0::Resolved;
+                            break PollState::Ready(String::new());
+                        }
+                        PollState::NotReady => break PollState::NotReady,
+                    }
+                }
+
+                State0::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn failing_step() {
+//     let txt = process::shell("sleep 1; echo oops >&2; exit 3").wait;
+//     println!("{txt}");
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn failing_step() -> impl Future<Output=String> {
+    Coroutine1::new()
+}
+        
+enum State1 {
+    Start,
+    Wait1(Box<dyn Future<Output = String>>),
+    Resolved,
+}
+
+struct Coroutine1 {
+    state: State1,
+}
+
+impl Coroutine1 {
+    fn new() -> Self {
+        Self { state: State1::Start }
+    }
+}
+
+
+impl Future for Coroutine1 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State1::Start => {
+                    // ---- Code you actually wrote ----
+                
+                    // ---------------------------------
+                    let fut1 = Box::new( process::shell("sleep 1; echo oops >&2; exit 3"));
+                    self.state = State1::Wait1(fut1);
+                }
+
+                State1::Wait1(ref mut f1) => {
+                    match f1.poll(waker) {
+                        PollState::Ready(txt) => {
+                            // ---- Code you actually wrote ----
+                            println!("{txt}");
+
+                            // ---------------------------------
+                            self.state = State1::Resolved;
+                            break PollState::Ready(String::new());
+                        }
+                        PollState::NotReady => break PollState::NotReady,
+                    }
+                }
+
+                State1::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn async_main() {
+//     println!("Program starting");
+// 
+//     for i in 0..4 {
+//         executor::spawn(step(i));
+//     }
+//     executor::spawn(failing_step());
+//     executor::spawn(Upcase::new("hello from a pipe\n"));
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn async_main() -> impl Future<Output=String> {
+    Coroutine2::new()
+}
+        
+enum State2 {
+    Start,
+    Resolved,
+}
+
+struct Coroutine2 {
+    state: State2,
+}
+
+impl Coroutine2 {
+    fn new() -> Self {
+        Self { state: State2::Start }
+    }
+}
+
+
+impl Future for Coroutine2 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State2::Start => {
+                    // ---- Code you actually wrote ----
+                    println!("Program starting");
+
+    for i in 0..4 {
+        executor::spawn(step(i));
+    }
+    executor::spawn(failing_step());
+    executor::spawn(Upcase::new("hello from a pipe\n"));
+
+                    // ---------------------------------
+                    self.state = State2::Resolved;
+                    break PollState::Ready(String::new());
+                }
+
+                State2::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//
+// Compares the two ways of running the reactor:
+//
+// * `thread`: the reactor runs its event loop on a dedicated thread and
+//   unparks the executor through `Waker::wake` (`runtime_two::init`).
+// * `inline`: the executor polls the reactor itself whenever it would park
+//   (`runtime_two::Builder::inline_reactor`).
+//
+// So that neither mode inherits threads or sockets from the other, each one
+// runs in a process of its own: without arguments, the benchmark runs itself
+// once for each mode and prints a summary:
+//
+//     cargo run --release --bin reactor_bench
+//
+// With `shards`, it measures instead how sharding the reactor changes lock
+// contention and throughput with many executors, the way
+// `http_waker_parallel` runs them: 12 executor threads, each making 1,000
+// requests. It runs itself once for each number of shards, so that every
+// configuration starts out with fresh lock counters as well:
+//
+//     cargo run --release --bin reactor_bench shards
+//
+// Every request goes to a tiny HTTP server on 127.0.0.1:7070 that the
+// benchmark starts on a separate thread. If the port is taken, we assume the
+// delayserver is running there and send our requests to it instead.
+//
+use std::{
+    cell::RefCell,
+    env,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    process::Command,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use learn_async_rust::{
+    executor::{self, Executor, Waker},
+    future_with_waker::{Future, PollState},
+    http_waker::HttpGetFuture,
+    reactor,
+    runtime_two::Builder,
+};
+
+// Number of requests made one after another to measure latency.
+const LATENCY_REQUESTS: usize = 500;
+// Number of tasks, each making `THROUGHPUT_REQUESTS` requests one after
+// another, used to measure throughput.
+const THROUGHPUT_TASKS: usize = 50;
+const THROUGHPUT_REQUESTS: usize = 20;
+
+// Number of executor threads for the `shards` benchmark. Each of them runs
+// `SHARDED_TASKS` tasks, each making `SHARDED_REQUESTS` requests one after
+// another, for 1,000 requests per executor. We keep the number of concurrent
+// connections well below the server's listen backlog, or we'd end up
+// measuring SYN retransmits instead of the reactor.
+const SHARDED_EXECUTORS: usize = 12;
+const SHARDED_TASKS: usize = 10;
+const SHARDED_REQUESTS: usize = 100;
+
+const USAGE: &str = "Usage: reactor_bench [thread|inline|shards [number]]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args[..] {
+        [] => run_each(&[&["thread"], &["inline"]]),
+        [mode @ ("thread" | "inline")] => run(mode),
+        ["shards"] => {
+            run_each(&[&["shards", "1"], &["shards", "4"], &["shards", "12"]])
+        }
+        ["shards", shards] => match shards.parse() {
+            Ok(shards) if shards > 0 => run_sharded(shards),
+            _ => println!("{USAGE}"),
+        },
+        _ => println!("{USAGE}"),
+    }
+}
+
+/// Runs each configuration in its own process and only shows our own output,
+/// not what the runtime prints along the way.
+fn run_each(configs: &[&[&str]]) {
+    let exe = env::current_exe().unwrap();
+    for args in configs {
+        let out = Command::new(&exe).args(*args).output().unwrap();
+        String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .filter_map(|l| l.strip_prefix("bench: "))
+            .for_each(|l| println!("{l}"));
+    }
+}
+
+fn run(mode: &str) {
+    start_server();
+    let mut executor = Builder::new().inline_reactor(mode == "inline").build();
+
+    // Latency: a single task that makes one request at a time.
+    let samples = Rc::new(RefCell::new(vec![]));
+    executor.block_on(Sequential::new(LATENCY_REQUESTS, samples.clone()));
+    let mut samples = samples.take();
+    samples.sort();
+    let percentile = |p: usize| samples[samples.len() * p / 100];
+
+    // Throughput: many tasks making requests concurrently.
+    let start = Instant::now();
+    executor.block_on(SpawnAll {
+        tasks: THROUGHPUT_TASKS,
+        requests: THROUGHPUT_REQUESTS,
+    });
+    let elapsed = start.elapsed();
+    let total = THROUGHPUT_TASKS * THROUGHPUT_REQUESTS;
+
+    println!(
+        "bench: {mode:>6}: latency p50 {:>8.1?}, p99 {:>8.1?} | \
+         throughput {:>8.0} req/s ({total} requests in {:.2?})",
+        percentile(50),
+        percentile(99),
+        total as f64 / elapsed.as_secs_f64(),
+        elapsed,
+    );
+}
+
+fn run_sharded(shards: usize) {
+    start_server();
+    reactor::start_sharded(shards);
+
+    let start = Instant::now();
+    let executors: Vec<_> = (0..SHARDED_EXECUTORS)
+        .map(|i| {
+            thread::Builder::new()
+                .name(format!("exec-{i}"))
+                .spawn(|| {
+                    Executor::new().block_on(SpawnAll {
+                        tasks: SHARDED_TASKS,
+                        requests: SHARDED_REQUESTS,
+                    })
+                })
+                .unwrap()
+        })
+        .collect();
+    executors.into_iter().for_each(|h| h.join().unwrap());
+    let elapsed = start.elapsed();
+
+    let stats = reactor::lock_stats();
+    let total = SHARDED_EXECUTORS * SHARDED_TASKS * SHARDED_REQUESTS;
+    println!(
+        "bench: {shards:>2} shard(s): {:>6.0} req/s ({total} requests in \
+         {elapsed:.2?}) | lock taken {:>6} times, contended {:>5} times \
+         ({:.2}%)",
+        total as f64 / elapsed.as_secs_f64(),
+        stats.acquisitions,
+        stats.contended,
+        stats.contended as f64 * 100.0 / stats.acquisitions as f64,
+    );
+    reactor::reactor().shutdown();
+}
+
+/// Serves a fixed response to every request until the process exits.
+fn start_server() {
+    let Ok(listener) = TcpListener::bind("127.0.0.1:7070") else {
+        return;
+    };
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            thread::spawn(move || {
+                // Read the request headers, up to the empty line.
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or(0) > 2 {
+                    line.clear();
+                }
+                let _ = stream.write_all(
+                    b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\
+                      connection: close\r\n\r\nHello",
+                );
+            });
+        }
+    });
+    // Give the server a moment to start accepting connections.
+    thread::sleep(Duration::from_millis(50));
+}
+
+/// Spawns `tasks` tasks for a throughput measurement, each making `requests`
+/// requests.
+struct SpawnAll {
+    tasks: usize,
+    requests: usize,
+}
+
+impl Future for SpawnAll {
+    type Output = String;
+
+    fn poll(&mut self, _waker: &Waker) -> PollState<Self::Output> {
+        for _ in 0..self.tasks {
+            let samples = Rc::new(RefCell::new(vec![]));
+            executor::spawn(Sequential::new(self.requests, samples));
+        }
+        PollState::Ready(String::new())
+    }
+}
+
+/// Makes `remaining` requests one after another and records how long each
+/// of them took.
+struct Sequential {
+    remaining: usize,
+    current: Option<(Instant, HttpGetFuture)>,
+    samples: Rc<RefCell<Vec<Duration>>>,
+}
+
+impl Sequential {
+    fn new(remaining: usize, samples: Rc<RefCell<Vec<Duration>>>) -> Self {
+        Self {
+            remaining,
+            current: None,
+            samples,
+        }
+    }
+}
+
+impl Future for Sequential {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+            if self.remaining == 0 {
+                break PollState::Ready(String::new());
+            }
+            let (start, request) = self.current.get_or_insert_with(|| {
+                (Instant::now(), HttpGetFuture::new("/0/bench"))
+            });
+            match request.poll(waker) {
+                PollState::Ready(_) => {
+                    self.samples.borrow_mut().push(start.elapsed());
+                    self.current = None;
+                    self.remaining -= 1;
+                }
+                PollState::NotReady => break PollState::NotReady,
+            }
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//
+// This is the template file that needs to be run through `corofy_waker` 
+// in order to generate the state machine transformation for the async code.
+//
+// Runs a couple of requests, shuts the reactor down, and then starts a new
+// one and does it all again. The reactor is shut down from another thread
+// while one of the tasks is still asleep, which cuts its sleep short.
+//
+
+use std::{thread, time::Duration};
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+    http_waker::Http, reactor, runtime_two, time,
+};
+
+fn main() {
+    for round in 0..2 {
+        println!("Round {round}: starting the runtime");
+        let mut executor = runtime_two::init();
+
+        // Shutting down joins the event loop thread, so once it returns, the
+        // reactor is gone for good and we're free to start a new one.
+        let reactor = reactor::reactor();
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(1500));
+            reactor.shutdown();
+            println!("Round {round}: reactor shut down");
+        });
+        executor.block_on(async_main());
+        stopper.join().unwrap();
+    }
+}
+
+
+
+
+
+
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn request(i: usize) {
+//     let path = format!("/{}/HelloWorld-{i}", i * 500);
+//     let txt = Http::get(path).wait;
+//     println!("{txt}");
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn request(i: usize) -> impl Future<Output=String> {
+    Coroutine0::new(i)
+}
+        
+enum State0 {
+    Start(usize),
+    Wait1(Box<dyn Future<Output = String>>),
+    Resolved,
+}
+
+struct Coroutine0 {
+    state: State0,
+}
+
+impl Coroutine0 {
+    fn new(i: usize) -> Self {
+        Self { state: State0::Start(i) }
+    }
+}
+
+
+impl Future for Coroutine0 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State0::Start(i) => {
+                    // ---- Code you actually wrote ----
+                    let path = format!("/{}/HelloWorld-{i}", i * 500);
+
+                    // ---------------------------------
+                    let fut1 = Box::new( Http::get(path));
+                    self.state = State0::Wait1(fut1);
+                }
+
+                State0::Wait1(ref mut f1) => {
+                    match f1.poll(waker) {
+                        PollState::Ready(txt) => {
+                            // ---- Code you actually wrote ----
+                            println!("{txt}");
+
+                            // ---------------------------------
+                            self.state = State0::Resolved;
+                            break PollState::Ready(String::new());
+                        }
+                        PollState::NotReady => break PollState::NotReady,
+                    }
+                }
+
+                State0::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn sleeper() {
+//     let txt = time::sleep(Duration::from_secs(10)).wait;
+//     println!("Woke up from a long sleep: {txt}");
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn sleeper() -> impl Future<Output=String> {
+    Coroutine1::new()
+}
+        
+enum State1 {
+    Start,
+    Wait1(Box<dyn Future<Output = String>>),
+    Resolved,
+}
+
+struct Coroutine1 {
+    state: State1,
+}
+
+impl Coroutine1 {
+    fn new() -> Self {
+        Self { state: State1::Start }
+    }
+}
+
+
+impl Future for Coroutine1 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State1::Start => {
+                    // ---- Code you actually wrote ----
+                
+                    // ---------------------------------
+                    let fut1 = Box::new( time::sleep(Duration::from_secs(10)));
+                    self.state = State1::Wait1(fut1);
+                }
+
+                State1::Wait1(ref mut f1) => {
+                    match f1.poll(waker) {
+                        PollState::Ready(txt) => {
+                            // ---- Code you actually wrote ----
+                            println!("Woke up from a long sleep: {txt}");
+
+                            // ---------------------------------
+                            self.state = State1::Resolved;
+                            break PollState::Ready(String::new());
+                        }
+                        PollState::NotReady => break PollState::NotReady,
+                    }
+                }
+
+                State1::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn async_main() {
+//     for i in 0..3 {
+//         executor::spawn(request(i));
+//     }
+//     executor::spawn(sleeper());
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn async_main() -> impl Future<Output=String> {
+    Coroutine2::new()
+}
+        
+enum State2 {
+    Start,
+    Resolved,
+}
+
+struct Coroutine2 {
+    state: State2,
+}
+
+impl Coroutine2 {
+    fn new() -> Self {
+        Self { state: State2::Start }
+    }
+}
+
+
+impl Future for Coroutine2 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State2::Start => {
+                    // ---- Code you actually wrote ----
+                    for i in 0..3 {
+        executor::spawn(request(i));
+    }
+    executor::spawn(sleeper());
+
+                    // ---------------------------------
+                    self.state = State2::Resolved;
+                    break PollState::Ready(String::new());
+                }
+
+                State2::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
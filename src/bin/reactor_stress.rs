@@ -0,0 +1,185 @@
+//
+// Stresses the reactor's event loop: `SOURCES` sockets all become readable at
+// the same moment, over and over again, and we measure how long it takes
+// until every task waiting on one of them has been woken and has read its
+// byte.
+//
+// Each round, a thread on the other end of the connections writes one byte to
+// each of them, and then waits until the tasks have read all of them. The
+// benchmark runs once for each event capacity in `CAPACITIES`, so we can see
+// what difference draining the events in smaller or larger batches makes:
+//
+//     cargo run --release --bin reactor_stress
+//
+use std::{
+    cell::Cell,
+    env,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    process::Command,
+    rc::Rc,
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+    io::PollEvented,
+    reactor::{self, Direction},
+    runtime_two,
+};
+use mio::Interest;
+
+const SOURCES: usize = 1000;
+const ROUNDS: usize = 200;
+const CAPACITIES: [usize; 4] = [16, 128, 1024, 4096];
+
+fn main() {
+    match env::args().nth(1).map(|s| s.parse::<usize>()) {
+        Some(Ok(capacity)) if capacity > 0 => run(capacity),
+        Some(_) => println!("Usage: reactor_stress [event capacity]"),
+        None => {
+            // Run each configuration in its own process, so that each of
+            // them starts out with fresh lock counters.
+            let exe = env::current_exe().unwrap();
+            for capacity in CAPACITIES {
+                let out = Command::new(&exe)
+                    .arg(capacity.to_string())
+                    .output()
+                    .unwrap();
+                String::from_utf8_lossy(&out.stdout)
+                    .lines()
+                    .filter_map(|l| l.strip_prefix("bench: "))
+                    .for_each(|l| println!("{l}"));
+            }
+        }
+    }
+}
+
+fn run(capacity: usize) {
+    let (clients, servers) = connect_pairs(SOURCES);
+    let mut executor =
+        runtime_two::Builder::new().event_capacity(capacity).build();
+
+    let (tx, rx) = mpsc::channel();
+    let writer = thread::spawn(move || write_rounds(servers, rx));
+    executor.block_on(SpawnAll {
+        clients: Some(clients),
+        done: tx,
+    });
+    let mut rounds = writer.join().unwrap();
+
+    rounds.sort();
+    let percentile = |p: usize| rounds[rounds.len() * p / 100];
+    let total: Duration = rounds.iter().sum();
+    let stats = reactor::lock_stats();
+    println!(
+        "bench: capacity {capacity:>4}: round p50 {:>8.1?}, p99 {:>8.1?} | \
+         {:>7.0} events/s | lock taken {:>6} times, contended {:>4} times",
+        percentile(50),
+        percentile(99),
+        (SOURCES * ROUNDS) as f64 / total.as_secs_f64(),
+        stats.acquisitions,
+        stats.contended,
+    );
+}
+
+/// Returns `n` connected pairs of sockets: the ends we read from in tasks,
+/// and the ends the writer thread writes to.
+fn connect_pairs(n: usize) -> (Vec<TcpStream>, Vec<TcpStream>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    (0..n)
+        .map(|_| {
+            let client = TcpStream::connect(addr).unwrap();
+            let (server, _) = listener.accept().unwrap();
+            server.set_nodelay(true).unwrap();
+            (client, server)
+        })
+        .unzip()
+}
+
+/// Writes a byte to every socket, `ROUNDS` times, and returns how long it
+/// took for the tasks to read them all in each round.
+fn write_rounds(
+    mut servers: Vec<TcpStream>,
+    done: mpsc::Receiver<()>,
+) -> Vec<Duration> {
+    // Wait until all the tasks have registered their sockets.
+    done.recv().unwrap();
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            for server in &mut servers {
+                server.write_all(b"x").unwrap();
+            }
+            done.recv().unwrap();
+            start.elapsed()
+        })
+        .collect()
+}
+
+/// Spawns a `Reader` for every socket.
+struct SpawnAll {
+    clients: Option<Vec<TcpStream>>,
+    done: Sender<()>,
+}
+
+impl Future for SpawnAll {
+    type Output = String;
+
+    fn poll(&mut self, _waker: &Waker) -> PollState<Self::Output> {
+        // Counts the bytes read in the current round, across all readers.
+        let count = Rc::new(Cell::new(0));
+        for client in self.clients.take().unwrap() {
+            client.set_nonblocking(true).unwrap();
+            let stream = mio::net::TcpStream::from_std(client);
+            executor::spawn(Reader {
+                stream: PollEvented::new(stream, Interest::READABLE).unwrap(),
+                remaining: ROUNDS,
+                count: count.clone(),
+                done: self.done.clone(),
+            });
+        }
+        self.done.send(()).unwrap();
+        PollState::Ready(String::new())
+    }
+}
+
+/// Reads one byte per round, and tells the writer thread when it was the
+/// last reader to do so in the current round.
+struct Reader {
+    stream: PollEvented<mio::net::TcpStream>,
+    remaining: usize,
+    count: Rc<Cell<usize>>,
+    done: Sender<()>,
+}
+
+impl Future for Reader {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        let mut buf = [0; 16];
+        while self.remaining > 0 {
+            match self
+                .stream
+                .poll_io(Direction::Read, waker, |s| s.read(&mut buf))
+            {
+                PollState::Ready(Ok(0)) => panic!("Connection closed early"),
+                PollState::Ready(Ok(n)) => {
+                    self.remaining -= n;
+                    self.count.set(self.count.get() + n);
+                    if self.count.get() == SOURCES {
+                        self.count.set(0);
+                        self.done.send(()).unwrap();
+                    }
+                }
+                PollState::Ready(Err(e)) => panic!("Error reading: {e}"),
+                PollState::NotReady => return PollState::NotReady,
+            }
+        }
+        PollState::Ready(String::new())
+    }
+}
@@ -0,0 +1,214 @@
+//
+// The delayserver, written on our own runtime instead of actix/tokio: it
+// accepts connections with `net::TcpListener`, and answers
+// `GET /{delay}/{message}` with `message` after `delay` milliseconds, using
+// the reactor's timers.
+//
+// The coroutines `corofy_waker` generates can't hold on to a listener or a
+// stream between wait points, and can only wait on futures that output a
+// `String` (see `future_with_waker::OrErrorMessage`), so the server is made
+// of hand-written tasks: one that accepts connections, and one for each
+// connection.
+//
+// It listens on port 7070 like the delayserver, so the other examples can
+// use either of them. Pass a different port as the first argument to run
+// both side by side:
+//
+//     cargo run --bin runtime_delayserver 7071
+//
+// or the path of a Unix socket to listen on instead:
+//
+//     cargo run --bin runtime_delayserver /tmp/delayserver.sock
+//
+use std::{
+    env, fs,
+    net::SocketAddr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+    io::{self, AsyncRead, AsyncWrite},
+    net::{TcpListener, UnixListener},
+    runtime_two,
+    time::{self, Sleep},
+};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(1);
+
+fn main() {
+    let arg = env::args().nth(1).unwrap_or_else(|| "7070".to_string());
+    let mut executor = runtime_two::init();
+    let server = match arg.parse::<u16>() {
+        Ok(port) => {
+            let addr = SocketAddr::from(([127, 0, 0, 1], port));
+            println!("Listening on {addr}");
+            Server::Tcp(TcpListener::bind(addr).unwrap())
+        }
+        Err(_) => {
+            // Binding fails if the socket is still there from an earlier run.
+            let _ = fs::remove_file(&arg);
+            println!("Listening on {arg}");
+            Server::Unix(UnixListener::bind(&arg).unwrap())
+        }
+    };
+    executor.block_on(server);
+}
+
+/// Accepts connections forever and spawns a task for each of them.
+enum Server {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Future for Server {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+            let accepted = match self {
+                Server::Tcp(listener) => match listener.poll_accept(waker) {
+                    PollState::Ready(result) => result.map(|(stream, _)| {
+                        executor::spawn(Connection::new(stream))
+                    }),
+                    PollState::NotReady => return PollState::NotReady,
+                },
+                Server::Unix(listener) => match listener.poll_accept(waker) {
+                    PollState::Ready(result) => result.map(|(stream, _)| {
+                        executor::spawn(Connection::new(stream))
+                    }),
+                    PollState::NotReady => return PollState::NotReady,
+                },
+            };
+            if let Err(e) = accepted {
+                eprintln!("Failed to accept: {e}");
+            }
+        }
+    }
+}
+
+enum State {
+    Reading,
+    Sleeping(Sleep),
+    // Writing the response. Holds the number of bytes written so far.
+    Writing(usize),
+}
+
+/// Serves a single request on a connection, then closes it.
+struct Connection<S> {
+    stream: S,
+    request: Vec<u8>,
+    response: Vec<u8>,
+    state: State,
+}
+
+impl<S: AsyncRead + AsyncWrite> Connection<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream,
+            request: vec![],
+            response: vec![],
+            state: State::Reading,
+        }
+    }
+
+    /// Reads until the end of the request headers. Resolves to `false` if
+    /// the client closes the connection before that.
+    fn poll_request(&mut self, waker: &Waker) -> PollState<bool> {
+        let mut buf = [0; 1024];
+        while !self.request.windows(4).any(|w| w == b"\r\n\r\n") {
+            match self.stream.poll_read(waker, &mut buf) {
+                PollState::Ready(Ok(0)) | PollState::Ready(Err(_)) => {
+                    return PollState::Ready(false);
+                }
+                PollState::Ready(Ok(n)) => {
+                    self.request.extend_from_slice(&buf[..n])
+                }
+                PollState::NotReady => return PollState::NotReady,
+            }
+        }
+        PollState::Ready(true)
+    }
+
+    /// Works out how long to wait and what to respond with.
+    fn parse_request(&mut self) -> Duration {
+        let request = String::from_utf8_lossy(&self.request);
+        let path = request
+            .lines()
+            .next()
+            .and_then(|line| line.strip_prefix("GET "))
+            .and_then(|rest| rest.split(' ').next())
+            .unwrap_or("");
+        let (delay_ms, message) =
+            match path.trim_start_matches('/').split_once('/') {
+                Some((delay, message)) => match delay.parse::<u64>() {
+                    Ok(delay) => (Some(delay), message),
+                    Err(_) => (None, ""),
+                },
+                None => (None, ""),
+            };
+
+        self.response = match delay_ms {
+            Some(delay_ms) => {
+                let count = COUNTER.fetch_add(1, Ordering::SeqCst);
+                println!("#{count} - {delay_ms}ms: {message}");
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\
+                     content-type: text/plain; charset=utf-8\r\n\
+                     connection: close\r\n\r\n{message}",
+                    message.len()
+                )
+            }
+            None => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\
+                     connection: close\r\n\r\n"
+                .to_string(),
+        }
+        .into_bytes();
+        Duration::from_millis(delay_ms.unwrap_or(0))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite> Future for Connection<S> {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+            match &mut self.state {
+                State::Reading => match self.poll_request(waker) {
+                    PollState::Ready(true) => {
+                        let delay = self.parse_request();
+                        self.state = State::Sleeping(time::try_sleep(delay));
+                    }
+                    PollState::Ready(false) => {
+                        return PollState::Ready(String::new());
+                    }
+                    PollState::NotReady => return PollState::NotReady,
+                },
+                State::Sleeping(sleep) => match sleep.poll(waker) {
+                    PollState::Ready(_) => self.state = State::Writing(0),
+                    PollState::NotReady => return PollState::NotReady,
+                },
+                State::Writing(written) => {
+                    let stream = &mut self.stream;
+                    match io::poll_write_all(
+                        stream,
+                        waker,
+                        &self.response,
+                        written,
+                    ) {
+                        PollState::Ready(result) => {
+                            if let Err(e) = result {
+                                eprintln!("Failed to respond: {e}");
+                            }
+                            // The connection is closed when we're dropped.
+                            return PollState::Ready(String::new());
+                        }
+                        PollState::NotReady => return PollState::NotReady,
+                    }
+                }
+            }
+        }
+    }
+}
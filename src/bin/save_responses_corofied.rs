@@ -0,0 +1,194 @@
+//
+// This is the template file that needs to be run through `corofy_waker` 
+// in order to generate the state machine transformation for the async code.
+//
+// Fetches a few responses from the delayserver concurrently, and saves each
+// of them to a file in `/tmp/responses` as soon as it arrives. Writing the
+// files happens on the blocking pool, so the executor thread never waits for
+// the disk while other requests are in flight.
+//
+
+use std::time::Instant;
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    fs,
+    future_with_waker::{Future, PollState},
+    http_waker::Http, runtime_two,
+};
+
+const DIR: &str = "/tmp/responses";
+
+fn main() {
+    std::fs::create_dir_all(DIR).unwrap();
+    let start = Instant::now();
+    let mut executor = runtime_two::init();
+    executor.block_on(async_main());
+    println!("\nELAPSED TIME: {}", start.elapsed().as_secs_f32());
+}
+
+// The coroutine doesn't keep `i` around after a wait, so we take the file
+// name from the body, which is the last line of the response.
+
+
+
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn request(i: usize) {
+//     let path = format!("/{}/Response-{i}", (5 - i) * 400);
+//     let txt = Http::get(path).wait;
+//     let name = txt.lines().last().unwrap_or("response").to_string();
+//     let note = fs::save(format!("{DIR}/{name}.txt"), txt).wait;
+//     println!("{note}");
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn request(i: usize) -> impl Future<Output=String> {
+    Coroutine0::new(i)
+}
+        
+enum State0 {
+    Start(usize),
+    Wait1(Box<dyn Future<Output = String>>),
+    Wait2(Box<dyn Future<Output = String>>),
+    Resolved,
+}
+
+struct Coroutine0 {
+    state: State0,
+}
+
+impl Coroutine0 {
+    fn new(i: usize) -> Self {
+        Self { state: State0::Start(i) }
+    }
+}
+
+
+impl Future for Coroutine0 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State0::Start(i) => {
+                    // ---- Code you actually wrote ----
+                    let path = format!("/{}/Response-{i}", (5 - i) * 400);
+
+                    // ---------------------------------
+                    let fut1 = Box::new( Http::get(path));
+                    self.state = State0::Wait1(fut1);
+                }
+
+                State0::Wait1(ref mut f1) => {
+                    match f1.poll(waker) {
+                        PollState::Ready(txt) => {
+                            // ---- Code you actually wrote ----
+                            let name = txt.lines().last().unwrap_or("response").to_string();
+
+                            // ---------------------------------
+                            let fut2 = Box::new( fs::save(format!("{DIR}/{name}.txt"), txt));
+                            self.state = State0::Wait2(fut2);
+                        }
+                        PollState::NotReady => break PollState::NotReady,
+                    }
+                }
+
+                State0::Wait2(ref mut f2) => {
+                    match f2.poll(waker) {
+                        PollState::Ready(note) => {
+                            // ---- Code you actually wrote ----
+                            println!("{note}");
+
+                            // ---------------------------------
+                            self.state = State0::Resolved;
+                            break PollState::Ready(String::new());
+                        }
+                        PollState::NotReady => break PollState::NotReady,
+                    }
+                }
+
+                State0::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn async_main() {
+//     println!("Program starting");
+// 
+//     for i in 0..5 {
+//         executor::spawn(request(i));
+//     }
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn async_main() -> impl Future<Output=String> {
+    Coroutine1::new()
+}
+        
+enum State1 {
+    Start,
+    Resolved,
+}
+
+struct Coroutine1 {
+    state: State1,
+}
+
+impl Coroutine1 {
+    fn new() -> Self {
+        Self { state: State1::Start }
+    }
+}
+
+
+impl Future for Coroutine1 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State1::Start => {
+                    // ---- Code you actually wrote ----
+                    println!("Program starting");
+
+    for i in 0..5 {
+        executor::spawn(request(i));
+    }
+
+                    // ---------------------------------
+                    self.state = State1::Resolved;
+                    break PollState::Ready(String::new());
+                }
+
+                State1::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
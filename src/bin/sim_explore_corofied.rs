@@ -0,0 +1,199 @@
+//
+// This is the template file that needs to be run through `corofy_waker` 
+// in order to generate the state machine transformation for the async code.
+//
+// The program has a bug: it assumes that the responses arrive in the order
+// the requests were sent. That's true most of the time against the
+// delayserver, but not always. The simulation executor finds a seed for
+// which it's not true, and that seed reproduces the failure every time.
+//
+
+use std::cell::RefCell;
+use std::time::Duration;
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+    sim::{self, Http, SimExecutor},
+};
+
+thread_local! {
+    // The order in which the responses arrived.
+    static RESPONSES: RefCell<Vec<String>> = const { RefCell::new(vec![]) };
+}
+
+fn run(seed: u64) -> anyhow::Result<()> {
+    RESPONSES.with(|r| r.borrow_mut().clear());
+    let mut executor =
+        SimExecutor::new(seed).with_jitter(Duration::from_millis(110));
+    executor.block_on(async_main())?;
+
+    let responses = RESPONSES.with(|r| r.borrow().clone());
+    let mut sorted = responses.clone();
+    sorted.sort();
+    anyhow::ensure!(
+        responses == sorted,
+        "responses arrived out of order: {responses:?}"
+    );
+    Ok(())
+}
+
+fn main() {
+    match sim::explore(0..1000, run) {
+        Ok(()) => println!("All seeds passed"),
+        Err(failure) => {
+            println!("Seed {} failed: {}", failure.seed, failure.error);
+            // Running the same seed again gives exactly the same result.
+            println!("Replay: {:?}", run(failure.seed));
+        }
+    }
+}
+
+
+
+
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn request(i: usize) {
+//     let path = format!("/{}/HelloWorld-{i}", i * 100);
+//     let txt = Http::get(path).wait;
+//     let body = txt.split("\r\n\r\n").nth(1).unwrap_or_default().to_string();
+//     println!("{:?} Response: {body}", sim::now());
+//     RESPONSES.with(|r| r.borrow_mut().push(body));
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn request(i: usize) -> impl Future<Output=String> {
+    Coroutine0::new(i)
+}
+        
+enum State0 {
+    Start(usize),
+    Wait1(Box<dyn Future<Output = String>>),
+    Resolved,
+}
+
+struct Coroutine0 {
+    state: State0,
+}
+
+impl Coroutine0 {
+    fn new(i: usize) -> Self {
+        Self { state: State0::Start(i) }
+    }
+}
+
+
+impl Future for Coroutine0 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State0::Start(i) => {
+                    // ---- Code you actually wrote ----
+                    let path = format!("/{}/HelloWorld-{i}", i * 100);
+
+                    // ---------------------------------
+                    let fut1 = Box::new( Http::get(path));
+                    self.state = State0::Wait1(fut1);
+                }
+
+                State0::Wait1(ref mut f1) => {
+                    match f1.poll(waker) {
+                        PollState::Ready(txt) => {
+                            // ---- Code you actually wrote ----
+                            let body = txt.split("\r\n\r\n").nth(1).unwrap_or_default().to_string();
+    println!("{:?} Response: {body}", sim::now());
+    RESPONSES.with(|r| r.borrow_mut().push(body));
+
+                            // ---------------------------------
+                            self.state = State0::Resolved;
+                            break PollState::Ready(String::new());
+                        }
+                        PollState::NotReady => break PollState::NotReady,
+                    }
+                }
+
+                State0::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn async_main() {
+//     for i in 0..5 {
+//         let future = request(i);
+//         executor::spawn(future);
+//     }
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn async_main() -> impl Future<Output=String> {
+    Coroutine1::new()
+}
+        
+enum State1 {
+    Start,
+    Resolved,
+}
+
+struct Coroutine1 {
+    state: State1,
+}
+
+impl Coroutine1 {
+    fn new() -> Self {
+        Self { state: State1::Start }
+    }
+}
+
+
+impl Future for Coroutine1 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State1::Start => {
+                    // ---- Code you actually wrote ----
+                    for i in 0..5 {
+        let future = request(i);
+        executor::spawn(future);
+    }
+
+                    // ---------------------------------
+                    self.state = State1::Resolved;
+                    break PollState::Ready(String::new());
+                }
+
+                State1::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//
+// Shows the executor's stall detection in action. `Forgetful` is a leaf
+// future with a bug: it returns `NotReady` without handing its Waker to
+// anyone, so nothing will ever wake its task again. Without stall detection,
+// the executor would park forever once the HTTP request is done, without
+// telling us why.
+//
+// Stalled tasks are reported as warnings, so we turn logging on for them.
+//
+use std::time::Duration;
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+    http_waker::Http,
+    runtime_two,
+};
+
+fn main() {
+    let mut executor = runtime_two::Builder::new()
+        .stall_timeout(Duration::from_secs(1))
+        .log_filter("warn")
+        .build();
+    executor.block_on(AsyncMain);
+}
+
+struct AsyncMain;
+
+impl Future for AsyncMain {
+    type Output = String;
+
+    fn poll(&mut self, _waker: &Waker) -> PollState<Self::Output> {
+        executor::spawn(Http::get("/500/HelloWorld".to_string()));
+        executor::spawn(Forgetful);
+        PollState::Ready(String::new())
+    }
+}
+
+struct Forgetful;
+
+impl Future for Forgetful {
+    type Output = String;
+
+    fn poll(&mut self, _waker: &Waker) -> PollState<Self::Output> {
+        // Bug: we should have stored the Waker somewhere.
+        PollState::NotReady
+    }
+}
@@ -0,0 +1,244 @@
+//
+// This is the template file that needs to be run through `corofy_waker` 
+// in order to generate the state machine transformation for the async code.
+//
+
+use chrono::Local;
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+    http_waker::Http, runtime_two,
+    task_local,
+    task_local::TaskLocals,
+};
+
+task_local! {
+    // Set by `async_main` for every request it spawns.
+    static REQUEST_ID: usize;
+}
+
+fn main() {
+    let mut executor = runtime_two::init();
+    executor.block_on(async_main());
+}
+
+
+
+
+
+
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn request(i: usize) {
+//     let path = format!("/{}/HelloWorld-{i}", i * 1000);
+//     REQUEST_ID.with(|id| println!("[request {id}] GET {path}"));
+//     let txt = Http::get(path).wait;
+//     let now = Local::now();
+//     REQUEST_ID.with(|id| println!("{now} [request {id}] Response:\n{txt}"));
+//     println!();
+//     // The child task sees the same `REQUEST_ID` as its parent.
+//     executor::spawn_with(done(), TaskLocals::inherit());
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn request(i: usize) -> impl Future<Output=String> {
+    Coroutine0::new(i)
+}
+        
+enum State0 {
+    Start(usize),
+    Wait1(Box<dyn Future<Output = String>>),
+    Resolved,
+}
+
+struct Coroutine0 {
+    state: State0,
+}
+
+impl Coroutine0 {
+    fn new(i: usize) -> Self {
+        Self { state: State0::Start(i) }
+    }
+}
+
+
+impl Future for Coroutine0 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State0::Start(i) => {
+                    // ---- Code you actually wrote ----
+                    let path = format!("/{}/HelloWorld-{i}", i * 1000);
+    REQUEST_ID.with(|id| println!("[request {id}] GET {path}"));
+
+                    // ---------------------------------
+                    let fut1 = Box::new( Http::get(path));
+                    self.state = State0::Wait1(fut1);
+                }
+
+                State0::Wait1(ref mut f1) => {
+                    match f1.poll(waker) {
+                        PollState::Ready(txt) => {
+                            // ---- Code you actually wrote ----
+                            let now = Local::now();
+    REQUEST_ID.with(|id| println!("{now} [request {id}] Response:\n{txt}"));
+    println!();
+    // The child task sees the same `REQUEST_ID` as its parent.
+    executor::spawn_with(done(), TaskLocals::inherit());
+
+                            // ---------------------------------
+                            self.state = State0::Resolved;
+                            break PollState::Ready(String::new());
+                        }
+                        PollState::NotReady => break PollState::NotReady,
+                    }
+                }
+
+                State0::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn done() {
+//     REQUEST_ID.with(|id| println!("[request {id}] Done"));
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn done() -> impl Future<Output=String> {
+    Coroutine1::new()
+}
+        
+enum State1 {
+    Start,
+    Resolved,
+}
+
+struct Coroutine1 {
+    state: State1,
+}
+
+impl Coroutine1 {
+    fn new() -> Self {
+        Self { state: State1::Start }
+    }
+}
+
+
+impl Future for Coroutine1 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State1::Start => {
+                    // ---- Code you actually wrote ----
+                    REQUEST_ID.with(|id| println!("[request {id}] Done"));
+
+                    // ---------------------------------
+                    self.state = State1::Resolved;
+                    break PollState::Ready(String::new());
+                }
+
+                State1::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn async_main() {
+//     println!("Program starting");
+// 
+//     for i in 0..5 {
+//         let future = request(i);
+//         executor::spawn_with(future, TaskLocals::new().with(&REQUEST_ID, i));
+//     }
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn async_main() -> impl Future<Output=String> {
+    Coroutine2::new()
+}
+        
+enum State2 {
+    Start,
+    Resolved,
+}
+
+struct Coroutine2 {
+    state: State2,
+}
+
+impl Coroutine2 {
+    fn new() -> Self {
+        Self { state: State2::Start }
+    }
+}
+
+
+impl Future for Coroutine2 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State2::Start => {
+                    // ---- Code you actually wrote ----
+                    println!("Program starting");
+
+    for i in 0..5 {
+        let future = request(i);
+        executor::spawn_with(future, TaskLocals::new().with(&REQUEST_ID, i));
+    }
+
+                    // ---------------------------------
+                    self.state = State2::Resolved;
+                    break PollState::Ready(String::new());
+                }
+
+                State2::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//
+// This is the template file that needs to be run through `corofy_waker` 
+// in order to generate the state machine transformation for the async code.
+//
+// Shows the timers in the reactor: a few tasks that sleep for different
+// amounts of time, and a hand-written future that uses an `Interval`.
+//
+
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+    runtime_two,
+    time::{self, Interval},
+};
+
+fn main() {
+    let start = Instant::now();
+    let mut executor = runtime_two::init();
+    executor.block_on(async_main());
+    println!("All done after {:?}", start.elapsed());
+}
+
+
+
+
+
+/// Prints a line on every tick of an `Interval`, `remaining` times.
+struct Ticker {
+    interval: Interval,
+    remaining: usize,
+    start: Instant,
+}
+
+impl Ticker {
+    fn new(period: Duration, remaining: usize) -> Self {
+        let start = Instant::now();
+        Self {
+            interval: time::interval(period),
+            remaining,
+            start,
+        }
+    }
+}
+
+impl Future for Ticker {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        while self.remaining > 0 {
+            match self.interval.poll_tick(waker) {
+                PollState::Ready(tick) => {
+                    println!("Tick at {:?}", tick - self.start);
+                    self.remaining -= 1;
+                }
+                PollState::NotReady => return PollState::NotReady,
+            }
+        }
+        PollState::Ready(String::new())
+    }
+}
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn nap(ms: u64) {
+//     println!("{} Sleeping for {ms}ms", Local::now().format("%T%.3f"));
+//     time::sleep(Duration::from_millis(ms)).wait;
+//     println!("{} Woke up", Local::now().format("%T%.3f"));
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn nap(ms: u64) -> impl Future<Output=String> {
+    Coroutine0::new(ms)
+}
+        
+enum State0 {
+    Start(u64),
+    Wait1(Box<dyn Future<Output = String>>),
+    Resolved,
+}
+
+struct Coroutine0 {
+    state: State0,
+}
+
+impl Coroutine0 {
+    fn new(ms: u64) -> Self {
+        Self { state: State0::Start(ms) }
+    }
+}
+
+
+impl Future for Coroutine0 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State0::Start(ms) => {
+                    // ---- Code you actually wrote ----
+                    println!("{} Sleeping for {ms}ms", Local::now().format("%T%.3f"));
+
+                    // ---------------------------------
+                    let fut1 = Box::new(time::sleep(Duration::from_millis(ms)));
+                    self.state = State0::Wait1(fut1);
+                }
+
+                State0::Wait1(ref mut f1) => {
+                    match f1.poll(waker) {
+                        PollState::Ready(_) => {
+                            // ---- Code you actually wrote ----
+                            println!("{} Woke up", Local::now().format("%T%.3f"));
+
+                            // ---------------------------------
+                            self.state = State0::Resolved;
+                            break PollState::Ready(String::new());
+                        }
+                        PollState::NotReady => break PollState::NotReady,
+                    }
+                }
+
+                State0::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
+
+
+// =================================
+// We rewrite this:
+// =================================
+    
+// coroutine fn async_main() {
+//     println!("Program starting");
+//     // Spawned longest first, so they can't finish in order by accident.
+//     for ms in [1000, 500, 250] {
+//         executor::spawn(nap(ms));
+//     }
+//     executor::spawn(Ticker::new(Duration::from_millis(200), 5));
+
+// }
+
+// =================================
+// Into this:
+// =================================
+
+fn async_main() -> impl Future<Output=String> {
+    Coroutine1::new()
+}
+        
+enum State1 {
+    Start,
+    Resolved,
+}
+
+struct Coroutine1 {
+    state: State1,
+}
+
+impl Coroutine1 {
+    fn new() -> Self {
+        Self { state: State1::Start }
+    }
+}
+
+
+impl Future for Coroutine1 {
+    type Output = String;
+
+    // Supress warnings about unused variables since `waker` may not always
+    // be used directly, and about the loop never looping in a coroutine
+    // without any wait points.
+    #[allow(unused, clippy::never_loop)]
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+        match self.state {
+                State1::Start => {
+                    // ---- Code you actually wrote ----
+                    println!("Program starting");
+    // Spawned longest first, so they can't finish in order by accident.
+    for ms in [1000, 500, 250] {
+        executor::spawn(nap(ms));
+    }
+    executor::spawn(Ticker::new(Duration::from_millis(200), 5));
+
+                    // ---------------------------------
+                    self.state = State1::Resolved;
+                    break PollState::Ready(String::new());
+                }
+
+                State1::Resolved => panic!("Polled a resolved future")
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//
+// Sends datagrams to a UDP echo server and waits for them to come back, with
+// both ends running as tasks on our runtime: the server waits for datagrams
+// with `recv_from`, and each client waits for its echo the same way. Nothing
+// here blocks, so every wait goes through the reactor, which wakes the task
+// with its `executor::Waker` once a datagram has arrived.
+//
+// The server waits a little before it echoes a datagram back, so that the
+// clients actually have to wait for their answers.
+//
+use std::{net::SocketAddr, time::Duration};
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+    net::UdpSocket,
+    runtime_two,
+    time::{self, Sleep},
+};
+
+const CLIENTS: usize = 3;
+const MESSAGES: usize = 3;
+
+fn main() {
+    let mut executor = runtime_two::init();
+    executor.block_on(AsyncMain);
+}
+
+struct AsyncMain;
+
+impl Future for AsyncMain {
+    type Output = String;
+
+    fn poll(&mut self, _waker: &Waker) -> PollState<Self::Output> {
+        let socket = UdpSocket::bind(([127, 0, 0, 1], 0).into()).unwrap();
+        let server = socket.local_addr().unwrap();
+        println!("Echo server listening on {server}");
+        executor::spawn(Echo::new(socket, CLIENTS * MESSAGES));
+        for id in 0..CLIENTS {
+            executor::spawn(Client::new(id, server));
+        }
+        PollState::Ready(String::new())
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+enum EchoState {
+    Receiving,
+    // Waiting before we echo the datagram back.
+    Sleeping(Sleep),
+    Sending,
+}
+
+/// Echoes `remaining` datagrams back to whoever sent them, then stops.
+struct Echo {
+    socket: UdpSocket,
+    buf: [u8; 1024],
+    // The size and sender of the datagram we're echoing.
+    datagram: (usize, SocketAddr),
+    remaining: usize,
+    state: EchoState,
+}
+
+impl Echo {
+    fn new(socket: UdpSocket, remaining: usize) -> Self {
+        Self {
+            socket,
+            buf: [0; 1024],
+            datagram: (0, ([0, 0, 0, 0], 0).into()),
+            remaining,
+            state: EchoState::Receiving,
+        }
+    }
+}
+
+impl Future for Echo {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        while self.remaining > 0 {
+            match &mut self.state {
+                EchoState::Receiving => {
+                    match self.socket.poll_recv_from(waker, &mut self.buf) {
+                        PollState::Ready(Ok(datagram)) => {
+                            self.datagram = datagram;
+                            let sleep =
+                                time::try_sleep(Duration::from_millis(100));
+                            self.state = EchoState::Sleeping(sleep);
+                        }
+                        PollState::Ready(Err(e)) => {
+                            panic!("Error receiving: {e}")
+                        }
+                        PollState::NotReady => return PollState::NotReady,
+                    }
+                }
+                EchoState::Sleeping(sleep) => match sleep.poll(waker) {
+                    PollState::Ready(_) => self.state = EchoState::Sending,
+                    PollState::NotReady => return PollState::NotReady,
+                },
+                EchoState::Sending => {
+                    let (len, from) = self.datagram;
+                    let buf = &self.buf[..len];
+                    match self.socket.poll_send_to(waker, buf, from) {
+                        PollState::Ready(Ok(_)) => {
+                            self.remaining -= 1;
+                            self.state = EchoState::Receiving;
+                        }
+                        PollState::Ready(Err(e)) => {
+                            panic!("Error sending: {e}")
+                        }
+                        PollState::NotReady => return PollState::NotReady,
+                    }
+                }
+            }
+        }
+        println!("Echo server done");
+        PollState::Ready(String::new())
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+enum ClientState {
+    Sending,
+    Receiving,
+}
+
+/// Sends `MESSAGES` datagrams to the server, one at a time, and waits for
+/// each of them to be echoed back.
+struct Client {
+    id: usize,
+    socket: UdpSocket,
+    server: SocketAddr,
+    sent: usize,
+    state: ClientState,
+}
+
+impl Client {
+    fn new(id: usize, server: SocketAddr) -> Self {
+        Self {
+            id,
+            socket: UdpSocket::bind(([127, 0, 0, 1], 0).into()).unwrap(),
+            server,
+            sent: 0,
+            state: ClientState::Sending,
+        }
+    }
+
+    fn message(&self) -> String {
+        format!("client {} message {}", self.id, self.sent)
+    }
+}
+
+impl Future for Client {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        while self.sent < MESSAGES {
+            match self.state {
+                ClientState::Sending => {
+                    let message = self.message();
+                    match self.socket.poll_send_to(
+                        waker,
+                        message.as_bytes(),
+                        self.server,
+                    ) {
+                        PollState::Ready(Ok(_)) => {
+                            println!("Sent: {message}");
+                            self.state = ClientState::Receiving;
+                        }
+                        PollState::Ready(Err(e)) => {
+                            panic!("Error sending: {e}")
+                        }
+                        PollState::NotReady => return PollState::NotReady,
+                    }
+                }
+                ClientState::Receiving => {
+                    let mut buf = [0; 1024];
+                    match self.socket.poll_recv_from(waker, &mut buf) {
+                        PollState::Ready(Ok((len, from))) => {
+                            let echo = String::from_utf8_lossy(&buf[..len]);
+                            println!("Echo from {from}: {echo}");
+                            assert_eq!(echo, self.message());
+                            self.sent += 1;
+                            self.state = ClientState::Sending;
+                        }
+                        PollState::Ready(Err(e)) => {
+                            panic!("Error receiving: {e}")
+                        }
+                        PollState::NotReady => return PollState::NotReady,
+                    }
+                }
+            }
+        }
+        PollState::Ready(String::new())
+    }
+}
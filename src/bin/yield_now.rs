@@ -0,0 +1,139 @@
+//
+// Shows how a task that always has work to do can share the executor with
+// its siblings by yielding.
+//
+// `Cruncher` does a CPU bound job in slices, and waits on `yield_now` after
+// each one, so that the executor gets to poll the other ready tasks before it
+// continues. `Ticker` only wants to print a line now and then, and does so
+// between the slices.
+//
+// Run with `NO_YIELD=1` to have the cruncher do the whole job in one poll
+// instead: the ticker doesn't get a look in until it's done.
+//
+use std::{env, time::Instant};
+
+use learn_async_rust::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState, YieldNow, yield_now},
+    runtime_two,
+};
+
+// How many slices the job is split into, and how much work each one is.
+const SLICES: u64 = 5;
+const SLICE_WORK: u64 = 20_000_000;
+
+fn main() {
+    let yielding = env::var_os("NO_YIELD").is_none();
+    let start = Instant::now();
+    let mut executor = runtime_two::init();
+    executor.block_on(AsyncMain { yielding, start });
+    println!("ELAPSED TIME: {}", start.elapsed().as_secs_f32());
+}
+
+struct AsyncMain {
+    yielding: bool,
+    start: Instant,
+}
+
+impl Future for AsyncMain {
+    type Output = String;
+
+    fn poll(&mut self, _waker: &Waker) -> PollState<Self::Output> {
+        executor::spawn(Cruncher {
+            yielding: self.yielding,
+            start: self.start,
+            slice: 0,
+            sum: 0,
+            yield_now: None,
+        });
+        executor::spawn(Ticker {
+            start: self.start,
+            ticks: 0,
+            yield_now: None,
+        });
+        PollState::Ready(String::new())
+    }
+}
+
+/// Sums up a lot of numbers, a slice at a time.
+struct Cruncher {
+    yielding: bool,
+    start: Instant,
+    slice: u64,
+    sum: u64,
+    // Set while we're giving the other tasks their turn.
+    yield_now: Option<YieldNow>,
+}
+
+impl Future for Cruncher {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+            if let Some(yielding) = &mut self.yield_now {
+                match yielding.poll(waker) {
+                    PollState::Ready(_) => self.yield_now = None,
+                    PollState::NotReady => return PollState::NotReady,
+                }
+            }
+            if self.slice == SLICES {
+                println!(
+                    "{:>6.3}s cruncher: sum {}",
+                    elapsed(self.start),
+                    self.sum
+                );
+                return PollState::Ready(String::new());
+            }
+
+            let from = self.slice * SLICE_WORK;
+            for n in from..from + SLICE_WORK {
+                self.sum = self.sum.wrapping_add(std::hint::black_box(n));
+            }
+            self.slice += 1;
+            println!(
+                "{:>6.3}s cruncher: slice {}/{SLICES} done",
+                elapsed(self.start),
+                self.slice
+            );
+            if self.yielding {
+                self.yield_now = Some(yield_now());
+            }
+        }
+    }
+}
+
+/// Prints a line every time it's polled, and yields in between.
+struct Ticker {
+    start: Instant,
+    ticks: u64,
+    yield_now: Option<YieldNow>,
+}
+
+impl Future for Ticker {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+            if let Some(yielding) = &mut self.yield_now {
+                match yielding.poll(waker) {
+                    PollState::Ready(_) => self.yield_now = None,
+                    PollState::NotReady => return PollState::NotReady,
+                }
+            }
+            if self.ticks == SLICES {
+                return PollState::Ready(String::new());
+            }
+            self.ticks += 1;
+            println!(
+                "{:>6.3}s ticker: tick {}",
+                elapsed(self.start),
+                self.ticks
+            );
+            self.yield_now = Some(yield_now());
+        }
+    }
+}
+
+fn elapsed(start: Instant) -> f32 {
+    start.elapsed().as_secs_f32()
+}
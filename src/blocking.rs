@@ -0,0 +1,184 @@
+//! A pool of threads for work that blocks, like file I/O.
+//!
+//! A task must never block the executor thread, because then none of the
+//! other tasks can run. `spawn_blocking` runs a closure on one of the pool's
+//! threads instead, and returns a future that resolves to its result. When
+//! the closure is done, the pool thread wakes the task with its `Waker`, just
+//! like the reactor does when a socket becomes ready.
+//!
+//! Threads are started when there's more work than idle threads, up to a
+//! limit (see `set_max_threads`), and stop again once they've been idle for
+//! `KEEP_ALIVE`.
+use std::{
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    executor::Waker,
+    future_with_waker::{Future, PollState},
+    logging, reactor,
+};
+
+/// How long an idle thread waits for more work before it stops.
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+const DEFAULT_MAX_THREADS: usize = 64;
+
+static POOL: Pool = Pool {
+    state: Mutex::new(State {
+        queue: VecDeque::new(),
+        threads: 0,
+        idle: 0,
+        next_name: 0,
+    }),
+    condvar: Condvar::new(),
+    max_threads: AtomicUsize::new(DEFAULT_MAX_THREADS),
+};
+
+/// Runs `f` on the blocking pool, and returns a future that resolves to what
+/// it returns.
+///
+/// The closure starts running straight away, whether the future is polled or
+/// not. Dropping the future doesn't stop it either: it runs to completion,
+/// and its result is thrown away.
+pub fn spawn_blocking<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let slot = Arc::new(Mutex::new(Slot {
+        result: None,
+        waker: None,
+    }));
+    let shared = slot.clone();
+    // An executor that drives the reactor inline waits for I/O events instead
+    // of parking, so waking the task isn't enough to get it polled. We have
+    // to interrupt the reactor as well.
+    let inline = reactor::inline_reactor();
+    POOL.submit(Box::new(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+        let mut slot = shared.lock().unwrap();
+        slot.result = Some(result);
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+            if let Some(reactor) = inline {
+                reactor.notify();
+            }
+        }
+    }));
+    JoinHandle { slot }
+}
+
+/// Sets how many threads the pool may run at once. Work that arrives while
+/// they're all busy waits in a queue. The default is 64.
+pub fn set_max_threads(max: usize) {
+    assert!(max > 0, "the blocking pool needs at least one thread");
+    POOL.max_threads.store(max, Ordering::Relaxed);
+}
+
+// -----------------------------------------------------------------------------
+
+/// The future returned by `spawn_blocking`.
+///
+/// If the closure panics, the panic is passed on to whoever polls this.
+pub struct JoinHandle<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+// Shared between a `JoinHandle` and the closure it's waiting for.
+struct Slot<T> {
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.result.take() {
+            Some(Ok(value)) => PollState::Ready(value),
+            Some(Err(panic)) => {
+                drop(slot);
+                panic::resume_unwind(panic)
+            }
+            None => {
+                slot.waker = Some(waker.clone());
+                PollState::NotReady
+            }
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct Pool {
+    state: Mutex<State>,
+    // Signalled whenever a job is queued.
+    condvar: Condvar,
+    max_threads: AtomicUsize,
+}
+
+struct State {
+    queue: VecDeque<Job>,
+    // How many threads are running, and how many of them are waiting for
+    // work.
+    threads: usize,
+    idle: usize,
+    // Used to give every thread a different name.
+    next_name: usize,
+}
+
+impl Pool {
+    fn submit(&'static self, job: Job) {
+        let mut state = self.state.lock().unwrap();
+        state.queue.push_back(job);
+        // Start another thread if the idle ones can't take all the queued
+        // jobs, unless we're at the limit.
+        let max_threads = self.max_threads.load(Ordering::Relaxed);
+        if state.queue.len() > state.idle && state.threads < max_threads {
+            let name = format!("blocking-{}", state.next_name);
+            let spawned =
+                thread::Builder::new().name(name).spawn(move || self.run());
+            match spawned {
+                Ok(_) => {
+                    state.threads += 1;
+                    state.next_name += 1;
+                }
+                // The job stays queued for one of the running threads.
+                Err(e) => logging::warn!("Failed to start a thread: {e}"),
+            }
+        }
+        self.condvar.notify_one();
+    }
+
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(job) = state.queue.pop_front() {
+                drop(state);
+                job();
+                state = self.state.lock().unwrap();
+                continue;
+            }
+            state.idle += 1;
+            let (guard, timeout) =
+                self.condvar.wait_timeout(state, KEEP_ALIVE).unwrap();
+            state = guard;
+            state.idle -= 1;
+            if timeout.timed_out() && state.queue.is_empty() {
+                state.threads -= 1;
+                return;
+            }
+        }
+    }
+}
@@ -1,14 +1,67 @@
 use std::{
     cell::{Cell, RefCell},
     collections::HashMap,
-    sync::{Arc, Mutex},
-    thread::Thread,
+    panic::Location,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread::{self, Thread, ThreadId},
+    time::{Duration, Instant},
 };
 
-use crate::future_with_waker::{Future, PollState};
+use crate::{
+    future_with_waker::{Future, PollState},
+    logging,
+    reactor::{self, Reactor},
+    task_local::TaskLocals,
+    trace::{self, EventKind},
+};
 
 pub type Task = Box<dyn Future<Output = String>>;
 
+/// A task that can be sent to the executor from a different thread.
+pub type SendTask = Box<dyn Future<Output = String> + Send>;
+
+/// What the executor knows about a task besides its future. We use this to
+/// find tasks that are stuck.
+pub struct TaskInfo {
+    // Where in the program the task was spawned.
+    pub spawned_at: &'static Location<'static>,
+
+    // When the task was last polled, if it has been polled at all.
+    pub last_poll: Option<Instant>,
+
+    // Every Waker for the task holds a clone of this, so the number of
+    // Wakers that are still alive is the strong count minus the one we hold
+    // here. If there are none, nothing can ever wake the task again.
+    pub alive: Arc<()>,
+
+    // The number of I/O sources the task currently has registered with the
+    // reactor.
+    pub io_registrations: usize,
+
+    // Whether we've already reported the task as stalled.
+    pub reported: bool,
+}
+
+impl TaskInfo {
+    fn new(spawned_at: &'static Location<'static>) -> Self {
+        Self {
+            spawned_at,
+            last_poll: None,
+            alive: Arc::new(()),
+            io_registrations: 0,
+            reported: false,
+        }
+    }
+
+    /// Returns the number of Wakers for this task that are still alive.
+    pub fn live_wakers(&self) -> usize {
+        Arc::strong_count(&self.alive) - 1
+    }
+}
+
 thread_local! {
     // Executor that's currently running on this thread.
     static CURRENT_EXECUTOR: ExecutorCore = ExecutorCore::default();
@@ -37,26 +90,207 @@ pub struct ExecutorCore {
     // created on, a simple `Cell` will suffice in giving us the internal
     // mutability we need.
     pub next_id: Cell<usize>,
+
+    // The number of units of work the task that's currently being polled is
+    // still allowed to do before it has to give control back to the executor.
+    // `None` means that the executor runs without a poll budget. It's reset
+    // by the executor every time it polls a task, and leaf futures charge
+    // against it through `consume_budget`.
+    pub budget: Cell<Option<usize>>,
+
+    // The task-local values of every task that has any, by task ID.
+    pub locals: RefCell<HashMap<usize, TaskLocals>>,
+
+    // The ID of the task that's currently being polled, if any. This is how
+    // `task_local` knows whose values to hand out.
+    pub current_task: Cell<Option<usize>>,
+
+    // Tasks that have been sent to this executor from other threads through
+    // a `Handle`, waiting to be spawned.
+    pub injector: Arc<Injector>,
+
+    // Book keeping for every task, by task ID. See `TaskInfo`.
+    pub info: RefCell<HashMap<usize, TaskInfo>>,
 }
 
 /// Allows us to register new top-level futures with our executor from anywhere
 /// in our program.
+#[track_caller]
 pub fn spawn<F>(future: F)
 where
     F: Future<Output = String> + 'static,
 {
+    spawn_with(future, TaskLocals::new());
+}
+
+/// Like `spawn`, but the new task starts out with the task-local values in
+/// `locals`. Use `TaskLocals::inherit` to give a child task the values of the
+/// task that spawns it.
+#[track_caller]
+pub fn spawn_with<F>(future: F, locals: TaskLocals)
+where
+    F: Future<Output = String> + 'static,
+{
+    spawn_task(Box::new(future), locals, Location::caller());
+}
+
+fn spawn_task(
+    task: Task,
+    locals: TaskLocals,
+    spawned_at: &'static Location<'static>,
+) {
     CURRENT_EXECUTOR.with(|e| {
         // Get the next available ID.
         let id = e.next_id.get();
         // Assigns the ID to the future and store it in the HashMap.
-        e.tasks.borrow_mut().insert(id, Box::new(future));
+        e.tasks.borrow_mut().insert(id, task);
+        e.info.borrow_mut().insert(id, TaskInfo::new(spawned_at));
+        if !locals.is_empty() {
+            e.locals.borrow_mut().insert(id, locals);
+        }
         // Adds the ID that represents this task to `ready_queue`, so that
         // it's polled at least once (recall that Future traits in Rust don't
         // do anything unless they're polled at least once).
         e.ready_queue.lock().map(|mut q| q.push(id)).unwrap();
         // Increment the ID by one.
         e.next_id.set(id + 1);
+        trace::record(EventKind::Spawn, id);
+    });
+}
+
+/// Returns a `Handle` to the executor on the current thread, which other
+/// threads can use to spawn tasks onto it.
+pub fn handle() -> Handle {
+    CURRENT_EXECUTOR.with(|e| Handle::new(e.injector.clone()))
+}
+
+/// Runs `f` with the `ExecutorCore` of the current thread. This lets other
+/// executors in this crate (like the simulation executor in `sim`) drive the
+/// same tasks that `spawn` hands out, just in a different order.
+pub(crate) fn with_current<R>(f: impl FnOnce(&ExecutorCore) -> R) -> R {
+    CURRENT_EXECUTOR.with(f)
+}
+
+/// Creates a new Waker for the task with the given ID on the current thread.
+pub(crate) fn waker_for(id: usize) -> Waker {
+    let thread = std::thread::current();
+    let (ready_queue, alive) = CURRENT_EXECUTOR.with(|q| {
+        let alive = q
+            .info
+            .borrow()
+            .get(&id)
+            .map(|i| i.alive.clone())
+            .unwrap_or_default();
+        (q.ready_queue.clone(), alive)
+    });
+    Waker {
+        thread,
+        id,
+        ready_queue,
+        alive,
+    }
+}
+
+/// The task that registered an I/O source with the reactor. See
+/// `note_io_registration`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct IoOwner {
+    // Task IDs are only unique within an executor, so we need to know which
+    // one it was.
+    thread: ThreadId,
+    task: usize,
+}
+
+/// Lets the executor know that the task that's currently being polled has
+/// registered an I/O source with the reactor, and returns that task, if
+/// there is one. Pass it to `note_io_deregistration` once the source is
+/// deregistered.
+pub(crate) fn note_io_registration() -> Option<IoOwner> {
+    CURRENT_EXECUTOR
+        .try_with(|e| {
+            let task = e.current_task.get()?;
+            let mut info = e.info.borrow_mut();
+            info.get_mut(&task)?.io_registrations += 1;
+            Some(IoOwner {
+                thread: thread::current().id(),
+                task,
+            })
+        })
+        .ok()
+        .flatten()
+}
+
+/// Lets the executor know that the source `owner` registered has been
+/// deregistered.
+///
+/// This isn't necessarily happening while `owner` is being polled: a pooled
+/// connection, for example, is often closed while another task is, so the
+/// source is counted against the task that registered it, not the current
+/// one.
+pub(crate) fn note_io_deregistration(owner: IoOwner) {
+    // On another thread, the same ID belongs to a task of another executor.
+    if owner.thread != thread::current().id() {
+        return;
+    }
+    let _ = CURRENT_EXECUTOR.try_with(|e| {
+        if let Some(info) = e.info.borrow_mut().get_mut(&owner.task) {
+            info.io_registrations = info.io_registrations.saturating_sub(1);
+        }
+    });
+}
+
+/// Polls the task with the given ID. The task is marked as the current task
+/// for the duration of the poll so that it can get to its task-local values,
+/// and those values are dropped when the task finishes.
+pub(crate) fn poll_task(
+    id: usize,
+    future: &mut Task,
+    waker: &Waker,
+) -> PollState<String> {
+    CURRENT_EXECUTOR.with(|e| {
+        e.current_task.set(Some(id));
+        if let Some(info) = e.info.borrow_mut().get_mut(&id) {
+            info.last_poll = Some(Instant::now());
+            info.reported = false;
+        }
+    });
+    trace::record(EventKind::PollStart, id);
+    let state = future.poll(waker);
+    let ready = matches!(state, PollState::Ready(_));
+    trace::record(EventKind::PollEnd { ready }, id);
+    CURRENT_EXECUTOR.with(|e| {
+        e.current_task.set(None);
+        if ready {
+            e.locals.borrow_mut().remove(&id);
+            e.info.borrow_mut().remove(&id);
+        }
     });
+    state
+}
+
+/// Charges one unit of work against the poll budget of the task that's
+/// currently being polled.
+///
+/// Leaf futures that can keep making progress without ever returning
+/// `NotReady` (for example, a read loop on a socket that always has more data)
+/// call this for every unit of work they do. If the budget is exhausted we
+/// wake the task ourselves and return `false`, and the leaf future is
+/// expected to return `NotReady`. The task will then be polled again on the
+/// next tick of the executor, after its siblings have had their turn.
+///
+/// If the executor runs without a budget this always returns `true`.
+pub fn consume_budget(waker: &Waker) -> bool {
+    CURRENT_EXECUTOR.with(|e| match e.budget.get() {
+        None => true,
+        Some(0) => {
+            waker.wake();
+            false
+        }
+        Some(n) => {
+            e.budget.set(Some(n - 1));
+            true
+        }
+    })
 }
 
 // -----------------------------------------------------------------------------
@@ -71,17 +305,59 @@ where
 /// * Enable us to run several executors by having each run on its dedicated
 ///   OS thread.
 ///
-/// It's worth noting that our executor won't be fully multithreaded in the
-/// sense that tasks/futures can't be sent from one thread to another, and the
-/// different executor instances will not know of each other. Therefore,
-/// executors can't steal work from each other (no work stealing), and we can't
-/// rely on executors picking tasks from a global task queue.
+/// It's worth noting that our executor won't be fully multithreaded. A
+/// `Handle` lets any thread send a new task to a specific executor, but once
+/// a task has been spawned, it stays on that executor's thread until it
+/// finishes, and the different executor instances will not know of each
+/// other. Therefore, executors can't steal work from each other (no work
+/// stealing), and we can't rely on executors picking tasks from a global task
+/// queue.
 ///
-pub struct Executor {}
+pub struct Executor {
+    // The number of units of work a task is allowed to do each time it's
+    // polled. See `consume_budget`.
+    budget: Option<usize>,
+
+    // How long a task can go without being polled, while nothing can wake
+    // it, before we report it as stalled. `None` turns stall detection off.
+    stall_timeout: Option<Duration>,
+}
 
 impl Executor {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            budget: None,
+            stall_timeout: None,
+        }
+    }
+
+    /// Gives every task a poll budget of `budget` units of work per tick. A
+    /// task that keeps making progress can then no longer monopolise the
+    /// executor loop and starve its siblings.
+    ///
+    /// Panics if `budget` is zero, since a task could then never make any
+    /// progress at all.
+    pub fn budget(mut self, budget: usize) -> Self {
+        assert!(budget > 0, "`budget` must be non-zero");
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Turns on stall detection, a debug mode for finding tasks that will
+    /// never finish.
+    ///
+    /// A leaf future that returns `NotReady` has to make sure that its Waker
+    /// is called at some point, usually by handing it to the reactor. If it
+    /// forgets to, its task stays pending forever, and if there are no other
+    /// tasks the executor parks forever. With stall detection on, the
+    /// executor wakes up at least every `timeout` to look for tasks that
+    /// haven't been polled for `timeout`, have no Waker that's still alive
+    /// and no I/O source registered with the reactor. It logs the ID, the
+    /// spawn site and the time of the last poll of each of them as a warning,
+    /// so logging has to be turned on to see them (see `logging`).
+    pub fn detect_stalls(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = Some(timeout);
+        self
     }
 
     /// This is the entry point for our executor. Often, you will pass in one
@@ -94,14 +370,25 @@ impl Executor {
     /// tasks stay on the same OS thread. This means that the tasks won't be
     /// able to run in parallel, which in turn allows us to avoid any need for
     /// synchronization between tasks to avoid data races.
+    #[track_caller]
     pub fn block_on<F>(&mut self, future: F)
     where
         F: Future<Output = String> + 'static,
     {
         spawn(future);
+        // Handles on other threads have to interrupt the inline reactor to
+        // get our attention, so they need to know which one we wait in.
+        self.set_inline_reactor(reactor::inline_reactor());
 
         loop {
-            while let Some(id) = self.pop_ready() {
+            // Spawn the tasks other threads have sent us since the last tick.
+            self.spawn_injected();
+
+            // One tick of the executor polls every task that was ready when
+            // the tick started. Tasks that are woken while we're polling (for
+            // example, by `yield_now` or an exhausted poll budget) end up in
+            // the ready queue and are polled on the next tick.
+            for id in self.take_ready() {
                 // Remove future from the `tasks` collection.
                 let mut future = match self.get_future(id) {
                     Some(f) => f,
@@ -114,7 +401,8 @@ impl Executor {
                 // specific `Future` trait and a handle to the thread we are
                 // currently running on.
                 let waker = self.make_waker(id);
-                match future.poll(&waker) {
+                self.reset_budget();
+                match poll_task(id, &mut future, &waker) {
                     // If `NotReady` we insert the task back into the `tasks`
                     // collection.  When a `Future` trait returns `NotReady`,
                     // we know that it will arrange it so that `Waker::wake`
@@ -125,40 +413,143 @@ impl Executor {
                     // If the `Future` trait returns `Ready`, we simply continue
                     // to the next item in the ready queue. Since we took
                     // ownership of the future, this will drop the object before
-                    // we enter the next iteration of the `while let` loop.
+                    // we enter the next iteration of the `for` loop.
                     PollState::Ready(_) => continue,
                 }
             }
 
+            // Tasks that were spawned or woken during this tick are already
+            // waiting in the ready queue, so there's no point in parking.
+            if self.has_ready() {
+                continue;
+            }
+
             // Now that we've polled all the tasks in our ready queue, the first
             // thing we do is get a task count to see how many tasks we have
             // left.
             let task_count = self.task_count();
-            let name = self.get_thread_name();
-            if task_count > 0 {
+            // As long as another thread holds a `Handle` to us, it can still
+            // send us new tasks, so we have to wait for it even if we have
+            // no tasks left.
+            if task_count > 0 || self.has_handles() {
                 // If the task count is greater than 0, we park the thread.
                 // Parking the thread will yield control to the OS scheduler,
                 // and our `Executor` does nothing until it's woken up again.
-                println!(
-                    "{name}: {task_count} pending tasks, Sleep until notified."
+                logging::debug!(
+                    "{task_count} pending tasks, Sleep until notified."
                 );
-                std::thread::park();
+                trace::record(EventKind::Park, 0);
+                match (reactor::inline_reactor(), self.stall_timeout) {
+                    // When the reactor runs inline, there is no event loop
+                    // thread to unpark us, so instead of parking we wait for
+                    // events ourselves. The Wakers the reactor calls push the
+                    // IDs onto our ready queue just like before.
+                    // The reactor may have been restarted since we last
+                    // waited in it.
+                    (Some(reactor), timeout) => {
+                        self.set_inline_reactor(Some(reactor.clone()));
+                        reactor.turn(timeout)
+                    }
+                    // With stall detection on, we don't sleep for longer than
+                    // the stall timeout, so that we get a chance to look for
+                    // stalled tasks even if nothing ever wakes us.
+                    (None, Some(timeout)) => std::thread::park_timeout(timeout),
+                    (None, None) => std::thread::park(),
+                }
+                trace::record(EventKind::Unpark, 0);
+                if let Some(timeout) = self.stall_timeout {
+                    self.report_stalls(timeout);
+                }
             } else {
                 // If the task count is 0, we're done with our asynchronous
                 // program and exit the main loop.
-                println!("{name}: All tasks are finished");
+                logging::debug!("All tasks are finished");
                 break;
             }
         }
     }
 
-    /// Pops off an ID that's ready from the ready queue.
-    /// Since Waker pushes the ID to the back of the queue, and we pop off
-    /// from the back as well, we essentially get a Last In First Out (LIFO)
-    /// queue.
-    fn pop_ready(&self) -> Option<usize> {
+    fn set_inline_reactor(&self, reactor: Option<Arc<Reactor>>) {
+        CURRENT_EXECUTOR
+            .with(|e| *e.injector.reactor.lock().unwrap() = reactor);
+    }
+
+    /// Takes all the IDs that are ready from the ready queue, leaving it
+    /// empty. Since Waker pushes the ID to the back of the queue, and we hand
+    /// them out from the back as well, we essentially get a Last In First Out
+    /// (LIFO) queue.
+    fn take_ready(&self) -> impl Iterator<Item = usize> {
+        let ready = CURRENT_EXECUTOR.with(|q| {
+            q.ready_queue
+                .lock()
+                .map(|mut q| std::mem::take(&mut *q))
+                .unwrap()
+        });
+        ready.into_iter().rev()
+    }
+
+    /// Returns `true` if there are tasks waiting in the ready queue, or
+    /// waiting to be spawned from another thread.
+    fn has_ready(&self) -> bool {
+        CURRENT_EXECUTOR.with(|q| {
+            !q.ready_queue.lock().unwrap().is_empty()
+                || !q.injector.queue.lock().unwrap().is_empty()
+        })
+    }
+
+    /// Spawns all the tasks that are waiting in the injection queue.
+    fn spawn_injected(&self) {
+        let injected = CURRENT_EXECUTOR.with(|q| {
+            q.injector
+                .queue
+                .lock()
+                .map(|mut q| std::mem::take(&mut *q))
+                .unwrap()
+        });
+        for (task, spawned_at) in injected {
+            spawn_task(task, TaskLocals::new(), spawned_at);
+        }
+    }
+
+    /// Logs a warning (through `logging::warn!`, so only with logging turned
+    /// on at the `warn` level) for every task that hasn't been polled for
+    /// `timeout` and that nothing can wake up. Each stalled task is only
+    /// reported once, until it's polled again.
+    fn report_stalls(&self, timeout: Duration) {
+        CURRENT_EXECUTOR.with(|q| {
+            let mut info = q.info.borrow_mut();
+            let mut stalled: Vec<_> = info
+                .iter_mut()
+                .filter(|(_, i)| {
+                    !i.reported
+                        && i.live_wakers() == 0
+                        && i.io_registrations == 0
+                        && i.last_poll.is_some_and(|t| t.elapsed() >= timeout)
+                })
+                .collect();
+            stalled.sort_by_key(|(id, _)| **id);
+            for (id, i) in stalled {
+                i.reported = true;
+                let last_poll = i.last_poll.unwrap().elapsed();
+                logging::warn!(
+                    "task {id} is stalled: pending with no Waker and \
+                     no I/O registration. Spawned at {}, last polled \
+                     {last_poll:?} ago.",
+                    i.spawned_at
+                );
+            }
+        });
+    }
+
+    /// Returns `true` if there is a `Handle` to this executor somewhere.
+    fn has_handles(&self) -> bool {
         CURRENT_EXECUTOR
-            .with(|q| q.ready_queue.lock().map(|mut q| q.pop()).unwrap())
+            .with(|q| q.injector.handles.load(Ordering::Acquire) > 0)
+    }
+
+    /// Resets the poll budget of the task we're about to poll.
+    fn reset_budget(&self) {
+        CURRENT_EXECUTOR.with(|q| q.budget.set(self.budget));
     }
 
     /// Takes the ID of a top level future, removes the future from the `tasks`
@@ -171,13 +562,7 @@ impl Executor {
 
     /// Create a new Waker instance.
     fn make_waker(&self, id: usize) -> Waker {
-        let thread = std::thread::current();
-        let ready_queue = CURRENT_EXECUTOR.with(|q| q.ready_queue.clone());
-        Waker {
-            thread,
-            id,
-            ready_queue,
-        }
+        waker_for(id)
     }
 
     /// Taks an ID property and a Task property and inserts them into our
@@ -190,14 +575,6 @@ impl Executor {
     fn task_count(&self) -> usize {
         CURRENT_EXECUTOR.with(|q| q.tasks.borrow().len())
     }
-
-    /// Returns the name of the thread that this executor is running on.
-    fn get_thread_name(&self) -> String {
-        std::thread::current()
-            .name()
-            .unwrap_or_default()
-            .to_string()
-    }
 }
 
 // -----------------------------------------------------------------------------
@@ -215,6 +592,10 @@ pub struct Waker {
     // queue. We share this object with the executor, so that we can push
     // the task ID associated with the Waker onto that queue when it's ready.
     pub ready_queue: Arc<Mutex<Vec<usize>>>,
+
+    // Shared by all the Wakers of the task, so that the executor can tell
+    // whether any of them are still alive. See `TaskInfo`.
+    pub alive: Arc<()>,
 }
 
 impl Waker {
@@ -230,6 +611,102 @@ impl Waker {
             .lock()
             .map(|mut q| q.push(self.id))
             .unwrap();
+        trace::record(EventKind::Wake, self.id);
+        self.thread.unpark();
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// The injection queue of an executor, shared with all its `Handle`s.
+pub struct Injector {
+    // The tasks sent to the executor that it hasn't spawned yet.
+    queue: Mutex<Vec<(SendTask, &'static Location<'static>)>>,
+
+    // The thread the executor runs on, so that we can unpark it.
+    thread: Thread,
+
+    // The reactor the executor waits in instead of parking, if it runs
+    // inline. Set by `block_on`, since only the executor's thread can tell
+    // which reactor (or shard) is its own.
+    reactor: Mutex<Option<Arc<Reactor>>>,
+
+    // The number of `Handle`s to the executor that are still alive.
+    handles: AtomicUsize,
+}
+
+impl Default for Injector {
+    fn default() -> Self {
+        Self {
+            queue: Mutex::new(vec![]),
+            // `CURRENT_EXECUTOR` is created on the thread it belongs to, so
+            // this is the executor's thread.
+            thread: std::thread::current(),
+            reactor: Mutex::new(None),
+            handles: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Injector {
+    /// Wakes the executor up, whether it's parked or waiting in the inline
+    /// reactor.
+    fn notify(&self) {
         self.thread.unpark();
+        if let Some(reactor) = &*self.reactor.lock().unwrap() {
+            reactor.notify();
+        }
+    }
+}
+
+/// A handle to an executor that can be sent to, and used from, any thread.
+///
+/// `spawn` only works on the thread the executor runs on, because the task
+/// collection is only accessible from there. A `Handle` instead pushes the
+/// task onto the executor's injection queue and wakes the executor up. The
+/// executor then spawns the task at the start of its next tick.
+///
+/// While a `Handle` is alive, `Executor::block_on` won't return even if it
+/// runs out of tasks, since more could arrive at any time. Drop the handle
+/// when you're done sending tasks.
+pub struct Handle {
+    injector: Arc<Injector>,
+}
+
+impl Handle {
+    fn new(injector: Arc<Injector>) -> Self {
+        injector.handles.fetch_add(1, Ordering::AcqRel);
+        Self { injector }
+    }
+
+    /// Sends `future` to the executor to be spawned as a new task. Since the
+    /// future crosses a thread boundary, it has to be `Send`.
+    #[track_caller]
+    pub fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = String> + Send + 'static,
+    {
+        let spawned_at = Location::caller();
+        self.injector
+            .queue
+            .lock()
+            .map(|mut q| q.push((Box::new(future), spawned_at)))
+            .unwrap();
+        self.injector.notify();
+    }
+}
+
+impl Clone for Handle {
+    fn clone(&self) -> Self {
+        Self::new(self.injector.clone())
+    }
+}
+
+impl Drop for Handle {
+    /// Lets the executor know that there is one handle less, so that it can
+    /// finish if this was the last one and it has no tasks left.
+    fn drop(&mut self) {
+        self.injector.handles.fetch_sub(1, Ordering::AcqRel);
+        self.injector.notify();
     }
 }
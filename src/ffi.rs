@@ -16,6 +16,24 @@ unsafe extern "C" {
     ) -> i32;
 }
 
+// For waiting on child processes, in `process`.
+#[link(name = "c")]
+unsafe extern "C" {
+    #[cfg(target_os = "linux")]
+    pub fn syscall(number: i64, ...) -> i64;
+    pub fn waitid(idtype: i32, id: u32, info: *mut u8, options: i32) -> i32;
+}
+
+#[cfg(target_os = "linux")]
+pub const SYS_PIDFD_OPEN: i64 = 434;
+
+pub const P_PID: i32 = 1;
+pub const WEXITED: i32 = 4;
+#[cfg(target_os = "linux")]
+pub const WNOWAIT: i32 = 0x0100_0000;
+#[cfg(not(target_os = "linux"))]
+pub const WNOWAIT: i32 = 0x20;
+
 #[derive(Debug)]
 #[repr(C)]
 #[cfg_attr(target_arch = "x86_64", repr(packed))]
@@ -0,0 +1,246 @@
+//! File system operations that don't block the executor.
+//!
+//! Files can't be registered with the reactor: epoll (and so mio) considers
+//! a regular file always ready, and reading it blocks anyway if the data
+//! isn't cached. So every operation here runs on the blocking pool (see
+//! `blocking::spawn_blocking`), and the task is woken once it's done.
+use std::{
+    fmt,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    blocking::{self, JoinHandle},
+    executor::Waker,
+    future_with_waker::{Future, OrErrorMessage, PollState},
+    io::{AsyncRead, AsyncWrite},
+};
+
+/// The most we read or write in one go on the blocking pool.
+const MAX_BUF: usize = 64 * 1024;
+
+/// Returns a future that reads the whole file at `path`.
+pub fn read(path: impl AsRef<Path>) -> JoinHandle<io::Result<Vec<u8>>> {
+    let path = path.as_ref().to_owned();
+    blocking::spawn_blocking(move || std::fs::read(path))
+}
+
+/// Returns a future that reads the whole file at `path` into a `String`.
+pub fn read_to_string(
+    path: impl AsRef<Path>,
+) -> JoinHandle<io::Result<String>> {
+    let path = path.as_ref().to_owned();
+    blocking::spawn_blocking(move || std::fs::read_to_string(path))
+}
+
+/// Returns a future that writes `contents` to the file at `path`, replacing
+/// the file if it exists.
+pub fn write(
+    path: impl AsRef<Path>,
+    contents: impl Into<Vec<u8>>,
+) -> JoinHandle<io::Result<()>> {
+    let path = path.as_ref().to_owned();
+    let contents = contents.into();
+    blocking::spawn_blocking(move || std::fs::write(path, contents))
+}
+
+/// Like `write`, but resolves to a short note of what was written where, or
+/// to a description of the error if writing failed (see
+/// `future_with_waker::OrErrorMessage`).
+pub fn save(
+    path: impl AsRef<Path>,
+    contents: impl Into<Vec<u8>>,
+) -> impl Future<Output = String> {
+    let contents = contents.into();
+    let path = path.as_ref().to_owned();
+    let len = contents.len();
+    let what = format!("Writing {}", path.display());
+    let write = write(&path, contents);
+    OrErrorMessage::new(write, what, move |()| {
+        format!("Wrote {len} bytes to {}", path.display())
+    })
+}
+
+// -----------------------------------------------------------------------------
+
+/// An open file, which reads and writes through `AsyncRead` and `AsyncWrite`.
+///
+/// While an operation is in progress, the standard library file it wraps is
+/// out on the blocking pool, and comes back with the result.
+pub struct File {
+    // Exactly one of these is set at any time.
+    idle: Option<Idle>,
+    busy: Option<JoinHandle<(Idle, Op)>>,
+}
+
+// What's left between operations: the file, and whatever we've read from it
+// that the caller hasn't asked for yet.
+struct Idle {
+    file: std::fs::File,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+// The result of an operation on the blocking pool.
+enum Op {
+    Read(io::Result<()>),
+    Write(io::Result<usize>),
+}
+
+impl File {
+    /// Returns a future that opens the file at `path` for reading.
+    pub fn open(path: impl AsRef<Path>) -> JoinHandle<io::Result<File>> {
+        let path = path.as_ref().to_owned();
+        blocking::spawn_blocking(move || {
+            std::fs::File::open(path).map(File::from_std)
+        })
+    }
+
+    /// Returns a future that opens the file at `path` for writing, creating
+    /// it if it doesn't exist and truncating it if it does.
+    pub fn create(path: impl AsRef<Path>) -> JoinHandle<io::Result<File>> {
+        let path = path.as_ref().to_owned();
+        blocking::spawn_blocking(move || {
+            std::fs::File::create(path).map(File::from_std)
+        })
+    }
+
+    pub fn from_std(file: std::fs::File) -> Self {
+        Self {
+            idle: Some(Idle {
+                file,
+                buf: vec![],
+                pos: 0,
+            }),
+            busy: None,
+        }
+    }
+
+    /// Waits for the operation in progress, if there is one, and returns the
+    /// file and the operation's result.
+    fn poll_idle(
+        &mut self,
+        waker: &Waker,
+    ) -> PollState<(&mut Idle, Option<Op>)> {
+        let mut op = None;
+        if let Some(busy) = &mut self.busy {
+            match busy.poll(waker) {
+                PollState::Ready((idle, result)) => {
+                    self.idle = Some(idle);
+                    self.busy = None;
+                    op = Some(result);
+                }
+                PollState::NotReady => return PollState::NotReady,
+            }
+        }
+        PollState::Ready((self.idle.as_mut().unwrap(), op))
+    }
+
+    /// Hands the file to the blocking pool to run `op` on it.
+    fn start(&mut self, op: impl FnOnce(&mut Idle) -> Op + Send + 'static) {
+        let mut idle = self.idle.take().unwrap();
+        self.busy = Some(blocking::spawn_blocking(move || {
+            let result = op(&mut idle);
+            (idle, result)
+        }));
+    }
+}
+
+impl AsyncRead for File {
+    fn poll_read(
+        &mut self,
+        waker: &Waker,
+        buf: &mut [u8],
+    ) -> PollState<io::Result<usize>> {
+        loop {
+            let (idle, op) = match self.poll_idle(waker) {
+                PollState::Ready(ready) => ready,
+                PollState::NotReady => return PollState::NotReady,
+            };
+            if idle.pos < idle.buf.len() {
+                let n = buf.len().min(idle.buf.len() - idle.pos);
+                buf[..n].copy_from_slice(&idle.buf[idle.pos..idle.pos + n]);
+                idle.pos += n;
+                return PollState::Ready(Ok(n));
+            }
+            match op {
+                Some(Op::Read(Err(e))) => return PollState::Ready(Err(e)),
+                // A read that came back empty: we're at the end of the file.
+                Some(Op::Read(Ok(()))) => return PollState::Ready(Ok(0)),
+                // Nothing in progress, or a write that was abandoned before
+                // it finished. Either way, it's time to read.
+                Some(Op::Write(_)) | None => {}
+            }
+            if buf.is_empty() {
+                return PollState::Ready(Ok(0));
+            }
+            let len = buf.len().min(MAX_BUF);
+            self.start(move |idle| {
+                idle.buf.resize(len, 0);
+                idle.pos = 0;
+                match idle.file.read(&mut idle.buf) {
+                    Ok(n) => {
+                        idle.buf.truncate(n);
+                        Op::Read(Ok(()))
+                    }
+                    Err(e) => {
+                        idle.buf.clear();
+                        Op::Read(Err(e))
+                    }
+                }
+            });
+        }
+    }
+}
+
+impl AsyncWrite for File {
+    fn poll_write(
+        &mut self,
+        waker: &Waker,
+        buf: &[u8],
+    ) -> PollState<io::Result<usize>> {
+        loop {
+            let (idle, op) = match self.poll_idle(waker) {
+                PollState::Ready(ready) => ready,
+                PollState::NotReady => return PollState::NotReady,
+            };
+            if let Some(Op::Write(result)) = op {
+                return PollState::Ready(result);
+            }
+            // If we've read ahead of the caller, the file's position is past
+            // where they think it is, so we move it back before we write.
+            let unread = (idle.buf.len() - idle.pos) as i64;
+            let data = buf[..buf.len().min(MAX_BUF)].to_vec();
+            self.start(move |idle| {
+                idle.buf.clear();
+                idle.pos = 0;
+                let result = match unread {
+                    0 => Ok(0),
+                    n => idle.file.seek(SeekFrom::Current(-n)),
+                };
+                Op::Write(result.and_then(|_| idle.file.write(&data)))
+            });
+        }
+    }
+
+    /// Writes go straight to the file, so there's nothing to flush, but we
+    /// wait for an operation that's still in progress.
+    fn poll_flush(&mut self, waker: &Waker) -> PollState<io::Result<()>> {
+        match self.poll_idle(waker) {
+            PollState::Ready((_, Some(Op::Write(Err(e))))) => {
+                PollState::Ready(Err(e))
+            }
+            PollState::Ready(_) => PollState::Ready(Ok(())),
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+impl fmt::Debug for File {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("File")
+            .field("busy", &self.busy.is_some())
+            .finish()
+    }
+}
@@ -1,4 +1,6 @@
-use crate::executor::Waker;
+use std::fmt;
+
+use crate::{executor::Waker, logging};
 
 pub trait Future {
     type Output;
@@ -9,3 +11,86 @@ pub enum PollState<T> {
     Ready(T),
     NotReady,
 }
+
+// -----------------------------------------------------------------------------
+
+/// A future that gives control back to the executor once before it resolves.
+pub struct YieldNow {
+    yielded: bool,
+}
+
+/// Returns a future that lets the executor poll the other ready tasks before
+/// the current task continues. A task that always has work to do can use this
+/// to avoid monopolising the executor loop.
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+impl Future for YieldNow {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        if self.yielded {
+            return PollState::Ready(String::new());
+        }
+        // Wake ourselves straight away so that we end up in the ready queue
+        // again, but return `NotReady` so that the executor moves on to the
+        // other tasks first.
+        self.yielded = true;
+        waker.wake();
+        PollState::NotReady
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// Wraps a future that can fail, and resolves to a `String` either way: what
+/// `on_ok` makes of its output if it succeeds, or `"Error: "` followed by the
+/// error if it fails. A failure is also logged as a warning, as `what` (for
+/// example "Writing out.txt") followed by the error.
+///
+/// This is for the coroutines `corofy_waker` generates. They can only wait
+/// on futures that output a `String`, since every wait point stores its
+/// future as a `Box<dyn Future<Output = String>>`. The futures meant to be
+/// waited on there (`Http::get`, `process::shell`, `fs::save` and
+/// `time::sleep`) are built with this adapter, and each of them has a
+/// counterpart that resolves to the `Result` itself (`Http::try_get`,
+/// `Command::output`, `fs::write` and `time::try_sleep`), for code that needs
+/// to tell success from failure.
+pub struct OrErrorMessage<F, M> {
+    future: F,
+    what: String,
+    on_ok: M,
+}
+
+impl<F, M> OrErrorMessage<F, M> {
+    pub fn new(future: F, what: impl Into<String>, on_ok: M) -> Self {
+        Self {
+            future,
+            what: what.into(),
+            on_ok,
+        }
+    }
+}
+
+impl<F, M, T, E> Future for OrErrorMessage<F, M>
+where
+    F: Future<Output = Result<T, E>>,
+    M: FnMut(T) -> String,
+    E: fmt::Display,
+{
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        match self.future.poll(waker) {
+            PollState::Ready(Ok(output)) => {
+                PollState::Ready((self.on_ok)(output))
+            }
+            PollState::Ready(Err(e)) => {
+                logging::warn!("{} failed: {e}", self.what);
+                PollState::Ready(format!("Error: {e}"))
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
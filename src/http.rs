@@ -1,12 +1,23 @@
-use chrono::Local;
-
-use crate::future::{Future, PollState};
+use crate::{
+    future::{Future, PollState},
+    http_response::{HttpResponse, ResponseParser},
+    http_waker::HttpError,
+    logging,
+    url::Url,
+};
 use std::io::{ErrorKind, Read, Write};
 
 pub struct Http;
 
 impl Http {
+    /// Sends a GET request and resolves to the response, as text.
     pub fn get(path: String) -> impl Future<Output = String> {
+        Text(HttpGetFuture::new(&path))
+    }
+
+    /// Sends a GET request and resolves to the parsed response, or to the
+    /// reason there isn't one.
+    pub fn get_response(path: String) -> HttpGetFuture {
         HttpGetFuture::new(&path)
     }
 }
@@ -14,9 +25,9 @@ impl Http {
 // This is our leaf future that will perform the HTTP GET request.
 pub struct HttpGetFuture {
     pub stream: Option<mio::net::TcpStream>,
-    // We'll read the data from the TcpStream and put it all in this buffer
+    // We'll feed the data from the TcpStream to the parser as it arrives,
     // until we've read all the data returned from the server.
-    pub buffer: Vec<u8>,
+    pub parser: ResponseParser,
     pub path: String,
 }
 
@@ -24,34 +35,41 @@ impl HttpGetFuture {
     pub fn new(path: &str) -> Self {
         Self {
             stream: None,
-            buffer: vec![],
+            parser: ResponseParser::new(),
             path: path.to_string(),
         }
     }
 
     /// Write a request to the server and initialize the stream.
-    pub fn write_request(&mut self) {
-        let stream = std::net::TcpStream::connect("127.0.0.1:7070").unwrap();
-        stream.set_nonblocking(true).unwrap();
+    ///
+    /// `path` can be a full URL or just a path, which goes to the
+    /// delayserver (see `url::Url`). Connecting resolves the host name and
+    /// blocks, just like writing the request does.
+    pub fn write_request(&mut self) -> Result<(), HttpError> {
+        let url = Url::parse(&self.path).map_err(HttpError::Url)?;
+        let stream = std::net::TcpStream::connect((url.host(), url.port()))
+            .map_err(HttpError::Connect)?;
+        stream.set_nonblocking(true).map_err(HttpError::Connect)?;
         let mut stream = mio::net::TcpStream::from_std(stream);
-        stream.write_all(get_req(&self.path).as_bytes()).unwrap();
+        let request = get_req(&url.host_header(), url.path());
+        stream
+            .write_all(request.as_bytes())
+            .map_err(HttpError::Io)?;
         self.stream = Some(stream);
+        Ok(())
     }
 }
 
 /// Implement the Future trait for our HttpGetFuture
 impl Future for HttpGetFuture {
-    type Output = String;
+    type Output = Result<HttpResponse, HttpError>;
 
     fn poll(&mut self) -> PollState<Self::Output> {
         if self.stream.is_none() {
-            let now = Local::now();
-            println!(
-                "{:?} [{}]: First poll, start operation",
-                now,
-                std::thread::current().name().unwrap()
-            );
-            self.write_request();
+            logging::debug!("First poll, start operation");
+            if let Err(e) = self.write_request() {
+                return PollState::Ready(Err(e));
+            }
             return PollState::NotReady;
         }
 
@@ -60,12 +78,24 @@ impl Future for HttpGetFuture {
             match self.stream.as_mut().unwrap().read(&mut buf) {
                 Ok(0) => {
                     // No more data to read
-                    let s = String::from_utf8_lossy(&self.buffer);
-                    break PollState::Ready(s.to_string());
+                    let parser = std::mem::take(&mut self.parser);
+                    break PollState::Ready(
+                        parser.finish().map_err(HttpError::Parse),
+                    );
                 }
                 Ok(n) => {
-                    // Read n bytes from the stream
-                    self.buffer.extend_from_slice(&buf[..n]);
+                    // Hand the n bytes we read to the parser
+                    if let Err(e) = self.parser.feed(&buf[..n]) {
+                        break PollState::Ready(Err(HttpError::Parse(e)));
+                    }
+                    // The response says where it ends, so we don't have to
+                    // wait for the server to close the connection.
+                    if self.parser.is_complete() {
+                        let parser = std::mem::take(&mut self.parser);
+                        break PollState::Ready(
+                            parser.finish().map_err(HttpError::Parse),
+                        );
+                    }
                     // Try to read more data from the stream
                     continue;
                 }
@@ -81,20 +111,41 @@ impl Future for HttpGetFuture {
                     continue;
                 }
                 Err(e) => {
-                    // An other error occurred; simply panic
-                    panic!("Error reading from stream: {}", e);
+                    // An other error occurred; the connection is no good
+                    break PollState::Ready(Err(HttpError::Io(e)));
                 }
             }
         }
     }
 }
 
+// Turns the response into text, for `Http::get`, or into an error message
+// like `http_waker::Http::get` does.
+struct Text(HttpGetFuture);
+
+impl Future for Text {
+    type Output = String;
+
+    fn poll(&mut self) -> PollState<Self::Output> {
+        match self.0.poll() {
+            PollState::Ready(Ok(response)) => {
+                PollState::Ready(response.to_string())
+            }
+            PollState::Ready(Err(e)) => {
+                logging::warn!("GET {} failed: {e}", self.0.path);
+                PollState::Ready(format!("Error: {e}"))
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------
 
-pub fn get_req(path: &str) -> String {
+pub fn get_req(host: &str, path: &str) -> String {
     format!(
         "GET {path} HTTP/1.1\r\n\
-             Host: localhost\r\n\
+             Host: {host}\r\n\
              Connection: close\r\n\
              \r\n"
     )
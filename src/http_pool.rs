@@ -0,0 +1,246 @@
+//! A pool of keep-alive connections for the `http_waker` client.
+//!
+//! When a response leaves the connection usable (see
+//! `ResponseParser::is_reusable`), the request hands it back to the pool
+//! instead of closing it, and the next request to the same `host:port`
+//! takes it from there instead of connecting again.
+//!
+//! The pool belongs to the thread it's used on. Connections are registered
+//! with the reactor on behalf of the tasks of one executor, and tasks never
+//! leave the thread they were spawned on, so every executor thread has a pool
+//! of its own and there's nothing to synchronise.
+//!
+//! Two limits apply to every pool (see `set_max_idle` and
+//! `set_max_per_host`):
+//!
+//! * How many idle connections it keeps. Past that, the one that's been idle
+//!   the longest is closed.
+//! * How many connections a host gets at once, idle or not. A request to a
+//!   host that's at the limit waits for one of the others to finish.
+//!
+//! An idle connection can break without us noticing, most often because the
+//! server closed it after its own keep-alive timeout. Connections that have
+//! been idle for longer than `IDLE_TIMEOUT` are closed instead of reused, and
+//! a request that fails on a reused connection before any of the response
+//! arrived is sent again on a new one.
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    executor::Waker, future_with_waker::PollState, http_waker::Stream,
+};
+
+/// How long a connection may be idle before we stop trusting it.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+const DEFAULT_MAX_IDLE: usize = 64;
+
+static MAX_IDLE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_IDLE);
+static MAX_PER_HOST: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+thread_local! {
+    static POOL: RefCell<Pool> = RefCell::new(Pool::default());
+}
+
+/// Sets how many idle connections a pool keeps, across all hosts. The
+/// default is 64. Zero turns pooling off: every connection is closed after
+/// its response.
+pub fn set_max_idle(max: usize) {
+    MAX_IDLE.store(max, Ordering::Relaxed);
+}
+
+/// Sets how many connections to the same host a pool allows at once. There's
+/// no limit by default.
+pub fn set_max_per_host(max: usize) {
+    assert!(max > 0, "a host needs at least one connection");
+    MAX_PER_HOST.store(max, Ordering::Relaxed);
+}
+
+/// What the pool on this thread has done so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Connections opened for a request.
+    pub opened: usize,
+    /// Requests that went out on an idle connection instead.
+    pub reused: usize,
+    /// Idle connections that were closed because they were too old, broken
+    /// or over the limit.
+    pub evicted: usize,
+}
+
+/// Returns the statistics of the pool on this thread.
+pub fn stats() -> PoolStats {
+    POOL.with(|pool| pool.borrow().stats)
+}
+
+// -----------------------------------------------------------------------------
+
+#[derive(Default)]
+struct Pool {
+    hosts: HashMap<String, Host>,
+    // How many connections are idle, across all hosts.
+    idle: usize,
+    stats: PoolStats,
+}
+
+#[derive(Default)]
+struct Host {
+    // The most recently used connection is last.
+    idle: Vec<Idle>,
+    // How many connections to this host are in use, including ones that are
+    // still being established.
+    in_use: usize,
+    // The tasks waiting for `in_use` to drop below the limit, with one Waker
+    // per task.
+    waiters: Vec<Waker>,
+}
+
+struct Idle {
+    stream: Stream,
+    since: Instant,
+}
+
+impl Host {
+    fn is_unused(&self) -> bool {
+        self.idle.is_empty() && self.in_use == 0 && self.waiters.is_empty()
+    }
+}
+
+impl Pool {
+    // Closes the connection that's been idle the longest, across all hosts.
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .hosts
+            .iter()
+            .filter_map(|(key, host)| Some((key, host.idle.first()?.since)))
+            .min_by_key(|(_, since)| *since)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = oldest {
+            let host = self.hosts.get_mut(&key).unwrap();
+            host.idle.remove(0);
+            self.idle -= 1;
+            self.stats.evicted += 1;
+            if host.is_unused() {
+                self.hosts.remove(&key);
+            }
+        }
+    }
+}
+
+/// Takes a connection to `key` (the `host:port` of the request) from the
+/// pool, if there's an idle one, or gets permission to open a new one. Waits
+/// if the host is at the limit.
+///
+/// Either way, the connection counts against the host's limit until the
+/// `Lease` is dropped or released.
+pub(crate) fn checkout(
+    key: &str,
+    waker: &Waker,
+) -> PollState<(Lease, Option<Stream>)> {
+    POOL.with(|pool| {
+        let pool = &mut *pool.borrow_mut();
+        let host = pool.hosts.entry(key.to_string()).or_default();
+
+        // The server has most likely closed connections this old already.
+        let now = Instant::now();
+        let before = host.idle.len();
+        host.idle.retain(|idle| now - idle.since < IDLE_TIMEOUT);
+        let expired = before - host.idle.len();
+        pool.idle -= expired;
+        pool.stats.evicted += expired;
+
+        let stream = match host.idle.pop() {
+            Some(idle) => {
+                pool.idle -= 1;
+                pool.stats.reused += 1;
+                Some(idle.stream)
+            }
+            None if host.in_use >= MAX_PER_HOST.load(Ordering::Relaxed) => {
+                // A waiting task can be polled again for some other reason
+                // before a connection frees up. The pool belongs to one
+                // executor, so the task ID tells us whether it's already
+                // queued, and if so we keep only its latest Waker.
+                let queued = host.waiters.iter_mut().find(|w| w.id == waker.id);
+                match queued {
+                    Some(queued) => *queued = waker.clone(),
+                    None => host.waiters.push(waker.clone()),
+                }
+                return PollState::NotReady;
+            }
+            None => None,
+        };
+        host.in_use += 1;
+        let lease = Lease {
+            key: key.to_string(),
+        };
+        PollState::Ready((lease, stream))
+    })
+}
+
+/// A connection that's in use, counted against its host's limit until it's
+/// dropped.
+pub(crate) struct Lease {
+    key: String,
+}
+
+impl Lease {
+    /// Records that a new connection was opened for this lease.
+    pub(crate) fn opened(&self) {
+        POOL.with(|pool| pool.borrow_mut().stats.opened += 1);
+    }
+
+    /// Records that the reused connection this lease was given turned out to
+    /// be broken.
+    pub(crate) fn evicted(&self) {
+        POOL.with(|pool| pool.borrow_mut().stats.evicted += 1);
+    }
+
+    /// Hands the connection back to the pool, for the next request to the
+    /// same host.
+    pub(crate) fn release(self, stream: Stream) {
+        POOL.with(|pool| {
+            let pool = &mut *pool.borrow_mut();
+            let max_idle = MAX_IDLE.load(Ordering::Relaxed);
+            if max_idle == 0 {
+                return;
+            }
+            if pool.idle >= max_idle {
+                pool.evict_oldest();
+            }
+            let host = pool.hosts.entry(self.key.clone()).or_default();
+            host.idle.push(Idle {
+                stream,
+                since: Instant::now(),
+            });
+            pool.idle += 1;
+        });
+        // Dropping the lease wakes anyone waiting for the host, who'll find
+        // the connection idle.
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        // The pool may already be gone if the thread is exiting.
+        let _ = POOL.try_with(|pool| {
+            let pool = &mut *pool.borrow_mut();
+            let Some(host) = pool.hosts.get_mut(&self.key) else {
+                return;
+            };
+            host.in_use -= 1;
+            // We don't know which of the waiting tasks are still around, so
+            // we wake them all, and the ones that don't get a connection
+            // wait again.
+            for waker in host.waiters.drain(..) {
+                waker.wake();
+            }
+            if host.is_unused() {
+                pool.hosts.remove(&self.key);
+            }
+        });
+    }
+}
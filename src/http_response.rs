@@ -0,0 +1,763 @@
+//! HTTP/1.1 responses, and the parser all three HTTP clients (`http`,
+//! `http_mio` and `http_waker`) use to read them.
+//!
+//! The parser is incremental: a leaf future feeds it whatever it has just
+//! read from the socket, however little that is, and the parser picks up
+//! where it left off the last time.
+//!
+//! It also works out where the body ends: after `Content-Length` bytes, after
+//! the last chunk of a `Transfer-Encoding: chunked` body, straight after the
+//! head for responses that never have a body (to a HEAD request, or with a
+//! 204 or 304 status), and only when the server closes the connection if the
+//! response doesn't say. So a client can stop reading as soon as the response
+//! is complete, instead of waiting for the server to close the socket.
+use std::{borrow::Cow, error::Error, fmt, mem};
+
+/// The most we accept for the status line and the headers together, so that
+/// a server that never ends its headers can't make us buffer forever. The
+/// same limit applies to the size line of a chunk, and to the trailers.
+const MAX_HEAD: usize = 64 * 1024;
+
+/// A response, as sent by the server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub reason: String,
+    /// The headers in the order they were sent, with their names as they
+    /// were sent. Use `header` to look one up by name.
+    pub headers: Vec<(String, String)>,
+    /// The body, with any transfer encoding removed.
+    pub body: Vec<u8>,
+    /// The headers sent after a chunked body, if there were any.
+    pub trailers: Vec<(String, String)>,
+}
+
+impl HttpResponse {
+    /// Returns the value of the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns `true` for a 2xx status.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// The body as text. Invalid UTF-8 is replaced with `U+FFFD`.
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+}
+
+/// Writes the response roughly the way it came over the wire, which is what
+/// the clients used to resolve to before they parsed it. The protocol version
+/// is always written as HTTP/1.1, and a chunked body is written decoded.
+impl fmt::Display for HttpResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP/1.1 {} {}\r\n", self.status, self.reason)?;
+        for (name, value) in &self.headers {
+            write!(f, "{name}: {value}\r\n")?;
+        }
+        write!(f, "\r\n{}", self.text())
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// Parses a response as it arrives.
+///
+/// Call `feed` with every chunk read from the connection until `is_complete`
+/// returns `true` or the server closes the connection, and then `finish`.
+#[derive(Debug, Default)]
+pub struct ResponseParser {
+    // Everything we've received that isn't part of the body, until we've
+    // found the end of the head. After that, the part of a chunk's size line
+    // or a trailer we've received so far.
+    buf: Vec<u8>,
+    // How far we've searched `buf` for the end of the head.
+    searched: usize,
+    // If the request was a HEAD request, the response has no body, whatever
+    // its headers say.
+    head_request: bool,
+    head: Option<Head>,
+    // Where the body ends. Only meaningful once we have the head.
+    framing: Framing,
+    body: Vec<u8>,
+    trailers: Vec<(String, String)>,
+    // Set if anything arrived after the end of the response.
+    overrun: bool,
+}
+
+// How the end of the body is marked.
+#[derive(Debug, Default)]
+enum Framing {
+    // By the server closing the connection.
+    #[default]
+    UntilClose,
+    // By its length. Holds how much of the body we're still waiting for, so
+    // a response without a body is `Length(0)`.
+    Length(usize),
+    Chunked(Chunked),
+}
+
+// Where we are in a chunked body.
+#[derive(Debug)]
+enum Chunked {
+    // Reading the line with the size of the next chunk.
+    Size,
+    // Reading a chunk. Holds how much of it is left.
+    Data(usize),
+    // Reading the line break after a chunk.
+    DataEnd,
+    // Reading the trailers after the last chunk, up to an empty line.
+    Trailers,
+    Done,
+}
+
+#[derive(Debug)]
+struct Head {
+    status: u16,
+    reason: String,
+    headers: Vec<(String, String)>,
+    // Whether the server is willing to keep the connection open after the
+    // response.
+    keep_alive: bool,
+}
+
+impl ResponseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a parser for the response to a HEAD request, which ends with
+    /// the head.
+    pub fn for_head() -> Self {
+        Self {
+            head_request: true,
+            ..Self::default()
+        }
+    }
+
+    /// Takes the next piece of the response.
+    ///
+    /// Anything that arrives after the end of the response is ignored.
+    pub fn feed(&mut self, data: &[u8]) -> Result<(), ParseError> {
+        if self.head.is_some() {
+            return self.parse_body(data);
+        }
+        self.buf.extend_from_slice(data);
+        self.parse_head()?;
+        if self.head.is_none() {
+            return Ok(());
+        }
+        // Whatever came after the head is the start of the body.
+        let rest = mem::take(&mut self.buf);
+        self.parse_body(&rest)
+    }
+
+    /// Returns `true` once the whole response has arrived. A response whose
+    /// body lasts until the server closes the connection is never complete.
+    pub fn is_complete(&self) -> bool {
+        self.head.is_some()
+            && matches!(
+                self.framing,
+                Framing::Length(0) | Framing::Chunked(Chunked::Done)
+            )
+    }
+
+    /// Returns the status code, once the head has been parsed.
+    pub fn status(&self) -> Option<u16> {
+        self.head.as_ref().map(|head| head.status)
+    }
+
+    /// Returns `true` if the connection can carry another request once this
+    /// response is complete: the server didn't ask to close it, the body had
+    /// a known end, and nothing arrived after it.
+    pub fn is_reusable(&self) -> bool {
+        let keep_alive = self
+            .head
+            .as_ref()
+            .is_some_and(|head| head.keep_alive && head.status != 101);
+        keep_alive && self.is_complete() && !self.overrun
+    }
+
+    /// Finishes the response, once it's complete or the server has closed
+    /// the connection. Fails if the server closed it before the end of the
+    /// body.
+    pub fn finish(self) -> Result<HttpResponse, ParseError> {
+        let head = self.head.ok_or(ParseError::Incomplete)?;
+        if !matches!(
+            self.framing,
+            Framing::UntilClose
+                | Framing::Length(0)
+                | Framing::Chunked(Chunked::Done)
+        ) {
+            return Err(ParseError::Incomplete);
+        }
+        Ok(HttpResponse {
+            status: head.status,
+            reason: head.reason,
+            headers: head.headers,
+            body: self.body,
+            trailers: self.trailers,
+        })
+    }
+
+    fn parse_head(&mut self) -> Result<(), ParseError> {
+        loop {
+            // Carry on where we stopped searching, but back up a little in
+            // case the end of the head was split between two chunks.
+            let start = self.searched.saturating_sub(2);
+            let end = find_head_end(&self.buf, start);
+            let Some((end, len)) = end else {
+                self.searched = self.buf.len();
+                if self.buf.len() > MAX_HEAD {
+                    return Err(ParseError::HeadTooLarge);
+                }
+                return Ok(());
+            };
+
+            let head = parse_head(&self.buf[..end])?;
+            let rest = self.buf.split_off(end + len);
+            self.buf.clear();
+            self.searched = 0;
+
+            // A 1xx response (like `100 Continue`) is only a preview, and the
+            // real response follows it.
+            if (100..200).contains(&head.status) && head.status != 101 {
+                self.buf = rest;
+                continue;
+            }
+            self.framing = self.framing(&head)?;
+            self.head = Some(head);
+            self.buf = rest;
+            return Ok(());
+        }
+    }
+
+    /// Works out how the end of the body is marked (RFC 9112, section 6.3).
+    fn framing(&self, head: &Head) -> Result<Framing, ParseError> {
+        // These never have a body, whatever the headers say. A 101 switches
+        // to another protocol, and what follows isn't ours to read.
+        if self.head_request || matches!(head.status, 101 | 204 | 304) {
+            return Ok(Framing::Length(0));
+        }
+
+        // A transfer encoding wins over a length. If chunked isn't the last
+        // encoding, the body ends when the connection does.
+        if let Some(encodings) = head.values("transfer-encoding").last() {
+            let last = encodings.rsplit(',').next().unwrap_or_default();
+            return Ok(match last.trim().eq_ignore_ascii_case("chunked") {
+                true => Framing::Chunked(Chunked::Size),
+                false => Framing::UntilClose,
+            });
+        }
+
+        // There may be several `Content-Length` headers, or a list in one,
+        // but they have to agree.
+        let mut length = None;
+        for value in head.values("content-length").flat_map(|v| v.split(',')) {
+            let invalid = || ParseError::InvalidContentLength(value.into());
+            let value = value.trim();
+            if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            let n = value.parse::<usize>().map_err(|_| invalid())?;
+            if length.is_some_and(|length| length != n) {
+                return Err(invalid());
+            }
+            length = Some(n);
+        }
+        Ok(length.map_or(Framing::UntilClose, Framing::Length))
+    }
+
+    fn parse_body(&mut self, mut data: &[u8]) -> Result<(), ParseError> {
+        while !data.is_empty() {
+            match &mut self.framing {
+                Framing::UntilClose => {
+                    self.body.extend_from_slice(data);
+                    return Ok(());
+                }
+                // The response is complete.
+                Framing::Length(0) | Framing::Chunked(Chunked::Done) => {
+                    self.overrun = true;
+                    return Ok(());
+                }
+                Framing::Length(left)
+                | Framing::Chunked(Chunked::Data(left)) => {
+                    let n = data.len().min(*left);
+                    self.body.extend_from_slice(&data[..n]);
+                    data = &data[n..];
+                    *left -= n;
+                    if let Framing::Chunked(state @ Chunked::Data(0)) =
+                        &mut self.framing
+                    {
+                        *state = Chunked::DataEnd;
+                    }
+                }
+                Framing::Chunked(state) => {
+                    let Some(line) = take_line(&mut self.buf, &mut data)?
+                    else {
+                        return Ok(());
+                    };
+                    *state = match state {
+                        Chunked::Size => match parse_chunk_size(&line)? {
+                            0 => Chunked::Trailers,
+                            size => Chunked::Data(size),
+                        },
+                        Chunked::DataEnd if line.is_empty() => Chunked::Size,
+                        Chunked::DataEnd => {
+                            return Err(ParseError::InvalidChunk(line));
+                        }
+                        Chunked::Trailers if line.is_empty() => Chunked::Done,
+                        Chunked::Trailers => {
+                            parse_header(&line, &mut self.trailers)?;
+                            Chunked::Trailers
+                        }
+                        Chunked::Data(_) | Chunked::Done => unreachable!(),
+                    };
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Takes the next line from `data`, adding it to what we've already received
+/// of it in `buf`. Returns `None` if the line hasn't ended yet.
+fn take_line(
+    buf: &mut Vec<u8>,
+    data: &mut &[u8],
+) -> Result<Option<String>, ParseError> {
+    let Some(end) = data.iter().position(|&b| b == b'\n') else {
+        buf.extend_from_slice(data);
+        *data = &[];
+        if buf.len() > MAX_HEAD {
+            return Err(ParseError::HeadTooLarge);
+        }
+        return Ok(None);
+    };
+    buf.extend_from_slice(&data[..end]);
+    *data = &data[end + 1..];
+    let line = mem::take(buf);
+    let line = line.strip_suffix(b"\r").unwrap_or(&line);
+    Ok(Some(String::from_utf8_lossy(line).into_owned()))
+}
+
+/// Parses the size line of a chunk, which is the size in hex, optionally
+/// followed by extensions after a `;`, which we ignore.
+fn parse_chunk_size(line: &str) -> Result<usize, ParseError> {
+    let size = line.split(';').next().unwrap_or_default().trim();
+    let invalid = || ParseError::InvalidChunk(line.to_string());
+    if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    usize::from_str_radix(size, 16).map_err(|_| invalid())
+}
+
+/// Finds the empty line that ends the head, and returns where it starts and
+/// how long it is. We also accept bare `\n` line endings, like most clients
+/// do.
+fn find_head_end(buf: &[u8], from: usize) -> Option<(usize, usize)> {
+    (from..buf.len()).find_map(|i| match &buf[i..] {
+        [b'\n', b'\n', ..] => Some((i, 2)),
+        [b'\n', b'\r', b'\n', ..] => Some((i, 3)),
+        _ => None,
+    })
+}
+
+impl Head {
+    /// The values of all the headers called `name`, ignoring case.
+    fn values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.headers
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn parse_head(head: &[u8]) -> Result<Head, ParseError> {
+    let head = String::from_utf8_lossy(head);
+    let mut lines = head.lines().map(|l| l.strip_suffix('\r').unwrap_or(l));
+
+    let status_line = lines.next().unwrap_or_default();
+    let invalid = || ParseError::InvalidStatusLine(status_line.to_string());
+    let (version, rest) = status_line.split_once(' ').ok_or_else(invalid)?;
+    if !version.starts_with("HTTP/1.") {
+        return Err(invalid());
+    }
+    let (code, reason) = rest.split_once(' ').unwrap_or((rest, ""));
+    if code.len() != 3 {
+        return Err(invalid());
+    }
+    let status = code.parse().map_err(|_| invalid())?;
+
+    let mut headers = vec![];
+    for line in lines {
+        parse_header(line, &mut headers)?;
+    }
+
+    // HTTP/1.1 keeps the connection open unless it's told not to, and
+    // HTTP/1.0 closes it unless it's told not to.
+    let connection = |option: &str| {
+        headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("connection"))
+            .flat_map(|(_, value)| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(option))
+    };
+    let keep_alive = match version {
+        "HTTP/1.0" => connection("keep-alive"),
+        _ => !connection("close"),
+    };
+
+    Ok(Head {
+        status,
+        reason: reason.to_string(),
+        headers,
+        keep_alive,
+    })
+}
+
+/// Parses a header line, or a trailer, and adds it to `headers`.
+fn parse_header(
+    line: &str,
+    headers: &mut Vec<(String, String)>,
+) -> Result<(), ParseError> {
+    // A line that starts with whitespace continues the previous header (the
+    // obsolete "line folding").
+    if line.starts_with([' ', '\t']) {
+        let (_, value) = headers
+            .last_mut()
+            .ok_or_else(|| ParseError::InvalidHeader(line.to_string()))?;
+        value.push(' ');
+        value.push_str(line.trim());
+        return Ok(());
+    }
+    let (name, value) = line
+        .split_once(':')
+        .filter(|(name, _)| is_token(name))
+        .ok_or_else(|| ParseError::InvalidHeader(line.to_string()))?;
+    headers.push((name.to_string(), value.trim().to_string()));
+    Ok(())
+}
+
+// Header names are "tokens": no whitespace, no separators.
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes().all(|b| {
+            b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+        })
+}
+
+// -----------------------------------------------------------------------------
+
+/// Why a response couldn't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidStatusLine(String),
+    InvalidHeader(String),
+    /// A `Content-Length` that isn't a number, or several that disagree.
+    InvalidContentLength(String),
+    /// A chunk size line that isn't hex, or a chunk that doesn't end with a
+    /// line break.
+    InvalidChunk(String),
+    HeadTooLarge,
+    /// The connection was closed before the response was complete.
+    Incomplete,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidStatusLine(line) => {
+                write!(f, "invalid status line `{line}`")
+            }
+            ParseError::InvalidHeader(line) => {
+                write!(f, "invalid header `{line}`")
+            }
+            ParseError::InvalidContentLength(value) => {
+                write!(f, "invalid content length `{value}`")
+            }
+            ParseError::InvalidChunk(line) => {
+                write!(f, "invalid chunk `{line}`")
+            }
+            ParseError::HeadTooLarge => {
+                write!(f, "headers longer than {MAX_HEAD} bytes")
+            }
+            ParseError::Incomplete => {
+                write!(f, "connection closed before the response was complete")
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What parsing a response gave us, and whether the connection could
+    // carry another request afterwards.
+    type Parsed = (Result<HttpResponse, ParseError>, bool);
+
+    /// Feeds `response` to `parser` in pieces of the given sizes, and then as
+    /// if the server had closed the connection.
+    fn parse_in(
+        mut parser: ResponseParser,
+        mut response: &[u8],
+        sizes: impl IntoIterator<Item = usize>,
+    ) -> Parsed {
+        for size in sizes {
+            if response.is_empty() {
+                break;
+            }
+            let (piece, rest) = response.split_at(size.min(response.len()));
+            if let Err(e) = parser.feed(piece) {
+                return (Err(e), false);
+            }
+            response = rest;
+        }
+        assert!(response.is_empty(), "the sizes don't cover the response");
+        let reusable = parser.is_reusable();
+        (parser.finish(), reusable)
+    }
+
+    /// Parses `response` whole, a byte at a time and in random splits, checks
+    /// that it makes no difference, and returns the result.
+    fn parse_with(parser: fn() -> ResponseParser, response: &str) -> Parsed {
+        let response = response.as_bytes();
+        let whole = parse_in(parser(), response, [response.len()]);
+        let bytes = parse_in(parser(), response, std::iter::repeat(1));
+        assert_eq!(whole, bytes, "fed a byte at a time");
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..100 {
+            let sizes = std::iter::repeat_with(|| rng.below(16) + 1);
+            let split = parse_in(parser(), response, sizes);
+            assert_eq!(whole, split, "fed in random pieces");
+        }
+        whole
+    }
+
+    fn parse(response: &str) -> Parsed {
+        parse_with(ResponseParser::new, response)
+    }
+
+    // A xorshift generator, which is all the randomness we need to split a
+    // response up.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    fn headers(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn content_length() {
+        let (response, reusable) = parse(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\
+             Content-Length: 12\r\n\r\nHello world!",
+        );
+        let response = response.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.reason, "OK");
+        assert_eq!(
+            response.headers,
+            headers(&[
+                ("Content-Type", "text/plain"),
+                ("Content-Length", "12")
+            ])
+        );
+        assert_eq!(response.header("content-type"), Some("text/plain"));
+        assert_eq!(response.body, b"Hello world!");
+        assert!(reusable);
+    }
+
+    #[test]
+    fn chunked_with_trailers() {
+        let (response, reusable) = parse(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+             5\r\nHello\r\n8;name=value\r\n chunked\r\n0\r\n\
+             X-Checksum: 1234\r\n\r\n",
+        );
+        let response = response.unwrap();
+        assert_eq!(response.body, b"Hello chunked");
+        assert_eq!(response.trailers, headers(&[("X-Checksum", "1234")]));
+        assert!(reusable);
+    }
+
+    #[test]
+    fn until_close() {
+        let (response, reusable) =
+            parse("HTTP/1.1 200 OK\nServer: test\n\nUntil close");
+        let response = response.unwrap();
+        assert_eq!(response.headers, headers(&[("Server", "test")]));
+        assert_eq!(response.body, b"Until close");
+        assert!(!reusable);
+    }
+
+    #[test]
+    fn no_body() {
+        for status in ["204 No Content", "304 Not Modified"] {
+            let (response, reusable) = parse(&format!(
+                "HTTP/1.1 {status}\r\nContent-Length: 12\r\n\r\n"
+            ));
+            assert_eq!(response.unwrap().body, b"");
+            assert!(reusable);
+        }
+        let (response, reusable) = parse_with(
+            ResponseParser::for_head,
+            "HTTP/1.1 200 OK\r\nContent-Length: 12\r\n\r\n",
+        );
+        assert_eq!(response.unwrap().body, b"");
+        assert!(reusable);
+    }
+
+    #[test]
+    fn skips_informational_responses() {
+        let (response, _) = parse(
+            "HTTP/1.1 100 Continue\r\n\r\n\
+             HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+        );
+        let response = response.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"ok");
+    }
+
+    #[test]
+    fn connection_header() {
+        let (_, reusable) = parse(
+            "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+        );
+        assert!(!reusable);
+        let (_, reusable) =
+            parse("HTTP/1.0 200 OK\r\nContent-Length: 0\r\n\r\n");
+        assert!(!reusable);
+        let (_, reusable) = parse(
+            "HTTP/1.0 200 OK\r\nConnection: keep-alive\r\n\
+             Content-Length: 0\r\n\r\n",
+        );
+        assert!(reusable);
+    }
+
+    #[test]
+    fn folded_header() {
+        let (response, _) =
+            parse("HTTP/1.1 200 OK\r\nX-Long: one\r\n  two\r\n\r\n");
+        assert_eq!(
+            response.unwrap().headers,
+            headers(&[("X-Long", "one two")])
+        );
+    }
+
+    #[test]
+    fn extra_bytes_after_the_body() {
+        let (response, reusable) = parse(
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHello, and more",
+        );
+        assert_eq!(response.unwrap().body, b"Hello");
+        assert!(!reusable);
+
+        let (response, reusable) = parse(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+             2\r\nok\r\n0\r\n\r\nextra",
+        );
+        assert_eq!(response.unwrap().body, b"ok");
+        assert!(!reusable);
+    }
+
+    #[test]
+    fn conflicting_content_length() {
+        let (response, _) = parse(
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\n\
+             Hello!",
+        );
+        assert_eq!(
+            response,
+            Err(ParseError::InvalidContentLength("6".to_string()))
+        );
+        let (response, _) =
+            parse("HTTP/1.1 200 OK\r\nContent-Length: 5, 6\r\n\r\nHello!");
+        assert_eq!(
+            response,
+            Err(ParseError::InvalidContentLength(" 6".to_string()))
+        );
+        // Repeating the same length is fine.
+        let (response, _) =
+            parse("HTTP/1.1 200 OK\r\nContent-Length: 5, 5\r\n\r\nHello");
+        assert_eq!(response.unwrap().body, b"Hello");
+    }
+
+    #[test]
+    fn invalid_content_length() {
+        let (response, _) =
+            parse("HTTP/1.1 200 OK\r\nContent-Length: -1\r\n\r\n");
+        assert_eq!(
+            response,
+            Err(ParseError::InvalidContentLength("-1".to_string()))
+        );
+    }
+
+    #[test]
+    fn bad_chunk_size() {
+        let (response, _) = parse(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+        );
+        assert_eq!(response, Err(ParseError::InvalidChunk("zz".to_string())));
+        let (response, _) = parse(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+             2\r\nokay\r\n",
+        );
+        assert_eq!(response, Err(ParseError::InvalidChunk("ay".to_string())));
+    }
+
+    #[test]
+    fn invalid_head() {
+        let (response, _) = parse("HTTP/2 200 OK\r\n\r\n");
+        assert_eq!(
+            response,
+            Err(ParseError::InvalidStatusLine("HTTP/2 200 OK".to_string()))
+        );
+        let (response, _) = parse("HTTP/1.1 200 OK\r\nNo colon\r\n\r\n");
+        assert_eq!(
+            response,
+            Err(ParseError::InvalidHeader("No colon".to_string()))
+        );
+    }
+
+    #[test]
+    fn head_too_large() {
+        let header = format!("X-Big: {}\r\n", "a".repeat(MAX_HEAD));
+        let (response, _) = parse(&format!("HTTP/1.1 200 OK\r\n{header}"));
+        assert_eq!(response, Err(ParseError::HeadTooLarge));
+    }
+
+    #[test]
+    fn incomplete() {
+        let (response, _) = parse("HTTP/1.1 200 OK\r\nContent-Length: 10\r\n");
+        assert_eq!(response, Err(ParseError::Incomplete));
+        let (response, _) =
+            parse("HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nhalf");
+        assert_eq!(response, Err(ParseError::Incomplete));
+        let (response, _) = parse(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+             5\r\nHello\r\n",
+        );
+        assert_eq!(response, Err(ParseError::Incomplete));
+    }
+}
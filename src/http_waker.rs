@@ -1,127 +1,544 @@
-use chrono::Local;
-use mio::Interest;
-
+#[cfg(unix)]
+use crate::net::{UnixConnect, UnixStream};
 use crate::{
-    executor::Waker,
-    future_with_waker::{Future, PollState},
-    reactor::reactor,
+    blocking::{self, JoinHandle},
+    executor::{self, Waker},
+    future_with_waker::{Future, OrErrorMessage, PollState},
+    http_pool::{self, Lease},
+    http_response::{HttpResponse, ParseError, ResponseParser},
+    io::{self, AsyncRead, AsyncWrite},
+    logging,
+    net::{Connect, TcpStream},
+    url::{self, Url, UrlError},
 };
-use std::io::{ErrorKind, Read, Write};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::{collections::VecDeque, error::Error, fmt, net::SocketAddr};
 
 pub struct Http;
 
 impl Http {
-    pub fn get(path: String) -> impl Future<Output = String> {
-        HttpGetFuture::new(&path)
+    /// Sends a GET request for `url` and resolves to the response. `url` is
+    /// either a full `http://` URL or just a path, which goes to the
+    /// delayserver (see `url::Url`).
+    ///
+    /// If the request fails, it resolves to a description of the error
+    /// instead (see `future_with_waker::OrErrorMessage`). Use `try_get` to
+    /// tell the two apart.
+    pub fn get(url: String) -> impl Future<Output = String> {
+        or_error_message(HttpGetFuture::new(&url))
+    }
+
+    /// Sends a GET request and resolves to the parsed response, or to the
+    /// reason it failed.
+    pub fn try_get(url: String) -> HttpGetFuture {
+        HttpGetFuture::new(&url)
+    }
+
+    /// Sends a HEAD request, and resolves to the head of the response.
+    pub fn try_head(url: String) -> HttpGetFuture {
+        HttpGetFuture::head(&url)
+    }
+
+    /// Like `get`, but sends the request to the server listening on the Unix
+    /// socket at `socket`.
+    #[cfg(unix)]
+    pub fn get_unix(
+        socket: impl Into<PathBuf>,
+        path: String,
+    ) -> impl Future<Output = String> {
+        or_error_message(HttpGetFuture::with_unix_socket(socket, &path))
+    }
+}
+
+/// Where to send a request.
+#[derive(Clone, Debug)]
+pub enum Target {
+    Tcp(SocketAddr),
+    /// A host name and a port. The name is resolved on the blocking pool
+    /// before we connect, and we try its addresses one after the other.
+    Host(String, u16),
+    /// The path of a Unix domain socket.
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Target {
+    /// What goes into the `Host` header of a request sent here.
+    fn host_header(&self) -> String {
+        match self {
+            Target::Tcp(addr) => addr.to_string(),
+            Target::Host(host, port) => format!("{host}:{port}"),
+            // There's no host name, but HTTP/1.1 requires the header.
+            #[cfg(unix)]
+            Target::Unix(_) => "localhost".to_string(),
+        }
     }
 }
 
-// This is our leaf future that will perform the HTTP GET request.
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Tcp(addr) => write!(f, "{addr}"),
+            Target::Host(host, port) => write!(f, "{host}:{port}"),
+            #[cfg(unix)]
+            Target::Unix(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// The connection a request is sent over.
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        &mut self,
+        waker: &Waker,
+        buf: &mut [u8],
+    ) -> PollState<std::io::Result<usize>> {
+        match self {
+            Stream::Tcp(stream) => stream.poll_read(waker, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.poll_read(waker, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        &mut self,
+        waker: &Waker,
+        buf: &[u8],
+    ) -> PollState<std::io::Result<usize>> {
+        match self {
+            Stream::Tcp(stream) => stream.poll_write(waker, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.poll_write(waker, buf),
+        }
+    }
+
+    fn poll_flush(&mut self, waker: &Waker) -> PollState<std::io::Result<()>> {
+        match self {
+            Stream::Tcp(stream) => stream.poll_flush(waker),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.poll_flush(waker),
+        }
+    }
+}
+
+// Connecting to a `Target`.
+enum Connecting {
+    Tcp(Connect),
+    #[cfg(unix)]
+    Unix(UnixConnect),
+}
+
+impl Future for Connecting {
+    type Output = std::io::Result<Stream>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        match self {
+            Connecting::Tcp(connect) => match connect.poll(waker) {
+                PollState::Ready(result) => {
+                    PollState::Ready(result.map(Stream::Tcp))
+                }
+                PollState::NotReady => PollState::NotReady,
+            },
+            #[cfg(unix)]
+            Connecting::Unix(connect) => match connect.poll(waker) {
+                PollState::Ready(result) => {
+                    PollState::Ready(result.map(Stream::Unix))
+                }
+                PollState::NotReady => PollState::NotReady,
+            },
+        }
+    }
+}
+
+/// Why a request failed.
+#[derive(Debug)]
+pub enum HttpError {
+    /// The URL couldn't be parsed.
+    Url(UrlError),
+    /// The host name couldn't be resolved.
+    Resolve(std::io::Error),
+    /// We couldn't connect to the server, for example because nothing is
+    /// listening on the port and the connection was refused.
+    Connect(std::io::Error),
+    /// The connection failed after it was established, for example because
+    /// the server reset it.
+    Io(std::io::Error),
+    /// The server's response wasn't valid HTTP.
+    Parse(ParseError),
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::Url(e) => write!(f, "invalid URL: {e}"),
+            HttpError::Resolve(e) => write!(f, "failed to resolve host: {e}"),
+            HttpError::Connect(e) => write!(f, "failed to connect: {e}"),
+            HttpError::Io(e) => write!(f, "connection failed: {e}"),
+            HttpError::Parse(e) => write!(f, "invalid response: {e}"),
+        }
+    }
+}
+
+impl Error for HttpError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HttpError::Url(e) => Some(e),
+            HttpError::Parse(e) => Some(e),
+            HttpError::Resolve(e)
+            | HttpError::Connect(e)
+            | HttpError::Io(e) => Some(e),
+        }
+    }
+}
+
+// Where we are in the request.
+enum State {
+    Start,
+    // Waiting for a connection from the pool, or for permission to open one.
+    Checkout,
+    // The URL was invalid. Holds the error until we've returned it.
+    Invalid(Option<UrlError>),
+    Resolving(JoinHandle<std::io::Result<Vec<SocketAddr>>>),
+    Connecting(Connecting),
+    // Sending the request. Holds how much of it we've sent so far.
+    Writing(usize),
+    // Reading the response.
+    Reading,
+}
+
+// This is our leaf future that will perform the HTTP GET request. It's a
+// thin layer over `net::TcpStream` (or `net::UnixStream`): connect, write the
+// request, and read the response until it's complete. Connections are kept
+// open for the next request to the same host when the server allows it (see
+// `http_pool`).
 pub struct HttpGetFuture {
-    pub stream: Option<mio::net::TcpStream>,
-    // We'll read the data from the TcpStream and put it all in this buffer
+    pub stream: Option<Stream>,
+    // We'll feed the data from the TcpStream to the parser as it arrives,
     // until we've read all the data returned from the server.
-    pub buffer: Vec<u8>,
+    pub parser: ResponseParser,
     pub path: String,
-    id: usize,
+    // The request method, and the value of the `Host` header.
+    method: &'static str,
+    host: String,
+    target: Target,
+    // The addresses we haven't tried to connect to yet, if the target's
+    // host name resolved to more than one.
+    addrs: VecDeque<SocketAddr>,
+    request: String,
+    state: State,
+    // Our place in the pool while we have a connection, or are opening one.
+    lease: Option<Lease>,
+    // Whether the connection came from the pool, and whether any of the
+    // response has arrived on it.
+    reused: bool,
+    received: bool,
 }
 
 impl HttpGetFuture {
-    pub fn new(path: &str) -> Self {
-        let id = reactor().next_id();
+    /// Sends the request to `url`: a full `http://` URL, or just a path,
+    /// which goes to the delayserver. If `url` is invalid, the future
+    /// resolves to `HttpError::Url`.
+    pub fn new(url: &str) -> Self {
+        match Url::parse(url) {
+            Ok(url) => {
+                let target = match url.socket_addr() {
+                    Some(addr) => Target::Tcp(addr),
+                    None => Target::Host(url.host().to_string(), url.port()),
+                };
+                let mut future = Self::with_target(target, url.path());
+                future.host = url.host_header();
+                future
+            }
+            Err(e) => {
+                // We never get as far as connecting, so the target doesn't
+                // matter.
+                let default = (url::DEFAULT_HOST, url::DEFAULT_PORT);
+                let target = Target::Host(default.0.into(), default.1);
+                let mut future = Self::with_target(target, url);
+                future.state = State::Invalid(Some(e));
+                future
+            }
+        }
+    }
+
+    /// Like `new`, but sends a HEAD request, which gets the head of the
+    /// response without the body.
+    pub fn head(url: &str) -> Self {
         Self {
-            stream: None,
-            buffer: vec![],
-            path: path.to_string(),
-            id,
+            method: "HEAD",
+            parser: ResponseParser::for_head(),
+            ..Self::new(url)
         }
     }
 
-    /// Write a request to the server and initialize the stream.
-    pub fn write_request(&mut self) {
-        let stream = std::net::TcpStream::connect("127.0.0.1:7070").unwrap();
-        stream.set_nonblocking(true).unwrap();
-        let mut stream = mio::net::TcpStream::from_std(stream);
-        stream.write_all(get_req(&self.path).as_bytes()).unwrap();
-        self.stream = Some(stream);
+    /// Sends the request to the server at `addr` instead of the delayserver.
+    pub fn with_addr(addr: SocketAddr, path: &str) -> Self {
+        Self::with_target(Target::Tcp(addr), path)
     }
-}
 
-/// Implement the Future trait for our HttpGetFuture
-impl Future for HttpGetFuture {
-    type Output = String;
+    /// Sends the request to the server listening on the Unix socket at
+    /// `socket`.
+    #[cfg(unix)]
+    pub fn with_unix_socket(socket: impl Into<PathBuf>, path: &str) -> Self {
+        Self::with_target(Target::Unix(socket.into()), path)
+    }
 
-    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
-        if self.stream.is_none() {
-            let now = Local::now();
-            println!(
-                "{:?} [{}]: First poll, start operation",
-                now,
-                std::thread::current().name().unwrap()
-            );
-            self.write_request();
-
-            let stream = self.stream.as_mut().unwrap();
-            reactor().register(stream, Interest::READABLE, self.id);
-            // Register the Waker with the reactor.
-            reactor().set_waker(waker, self.id);
+    pub fn with_target(target: Target, path: &str) -> Self {
+        Self {
+            stream: None,
+            parser: ResponseParser::new(),
+            path: path.to_string(),
+            method: "GET",
+            host: target.host_header(),
+            target,
+            addrs: VecDeque::new(),
+            request: String::new(),
+            state: State::Start,
+            lease: None,
+            reused: false,
+            received: false,
         }
+    }
 
-        let mut buf = vec![0; 4096];
+    /// Reads the response and feeds it to the parser, until it's complete or
+    /// the server closes the connection.
+    fn poll_response(
+        &mut self,
+        waker: &Waker,
+    ) -> PollState<Result<HttpResponse, HttpError>> {
+        let stream = self.stream.as_mut().unwrap();
+        let mut chunk = [0; 4096];
         loop {
-            match self.stream.as_mut().unwrap().read(&mut buf) {
-                Ok(0) => {
-                    // No more data to read
-                    let s = String::from_utf8_lossy(&self.buffer);
-                    // De-register the stream from our `Poll` instance when
-                    // we're done.
-                    reactor()
-                        .deregister(self.stream.as_mut().unwrap(), self.id);
-                    break PollState::Ready(s.to_string());
+            // `poll_read` stores our Waker with the reactor if there's no
+            // data yet.
+            match stream.poll_read(waker, &mut chunk) {
+                PollState::Ready(Ok(0)) => {
+                    let parser = std::mem::take(&mut self.parser);
+                    let response = parser.finish().map_err(HttpError::Parse);
+                    return PollState::Ready(response);
+                }
+                PollState::Ready(Ok(n)) => {
+                    self.received = true;
+                    if let Err(e) = self.parser.feed(&chunk[..n]) {
+                        return PollState::Ready(Err(HttpError::Parse(e)));
+                    }
+                    // The response says where it ends, so we don't have to
+                    // wait for the server to close the connection, and it
+                    // may let us send the next request over it.
+                    if self.parser.is_complete() {
+                        if self.parser.is_reusable() {
+                            let lease = self.lease.take().unwrap();
+                            lease.release(self.stream.take().unwrap());
+                        }
+                        let parser = std::mem::take(&mut self.parser);
+                        let response =
+                            parser.finish().map_err(HttpError::Parse);
+                        return PollState::Ready(response);
+                    }
+                    // Keep to our poll budget, like `io::poll_read_to_end`.
+                    if !executor::consume_budget(waker) {
+                        return PollState::NotReady;
+                    }
+                }
+                // The peer reset the connection, the reactor was shut down or
+                // something else went wrong. Either way, the response isn't
+                // coming.
+                PollState::Ready(Err(e)) => {
+                    return PollState::Ready(Err(HttpError::Io(e)));
                 }
-                Ok(n) => {
-                    // Read n bytes from the stream
-                    self.buffer.extend_from_slice(&buf[..n]);
-                    // Try to read more data from the stream
-                    continue;
+                PollState::NotReady => return PollState::NotReady,
+            }
+        }
+    }
+
+    /// Starts connecting to the target.
+    fn connect(&self) -> State {
+        match &self.target {
+            Target::Tcp(addr) => {
+                State::Connecting(Connecting::Tcp(TcpStream::connect(*addr)))
+            }
+            // Looking up a name blocks, so it's done on the blocking pool.
+            Target::Host(host, port) => {
+                let (host, port) = (host.clone(), *port);
+                State::Resolving(blocking::spawn_blocking(move || {
+                    url::resolve(&host, port)
+                }))
+            }
+            #[cfg(unix)]
+            Target::Unix(path) => State::Connecting(Connecting::Unix(
+                UnixStream::connect(path.clone()),
+            )),
+        }
+    }
+
+    /// Returns `true` if the request failed on a connection from the pool
+    /// before any of the response arrived. Most likely, the server closed the
+    /// connection while it was idle, and the request never reached it.
+    fn is_stale(&self) -> bool {
+        self.reused && !self.received
+    }
+
+    /// Throws the broken connection away, and sends the request again on a
+    /// new one. GET and HEAD requests are safe to repeat.
+    fn retry(&mut self) {
+        logging::debug!("Reused connection to {} is broken", self.target);
+        self.lease.as_ref().unwrap().evicted();
+        self.stream = None;
+        self.reused = false;
+        self.parser = match self.method {
+            "HEAD" => ResponseParser::for_head(),
+            _ => ResponseParser::new(),
+        };
+        self.state = self.connect();
+    }
+
+    fn poll_request(
+        &mut self,
+        waker: &Waker,
+    ) -> PollState<Result<HttpResponse, HttpError>> {
+        loop {
+            match &mut self.state {
+                State::Start => {
+                    logging::debug!("First poll, start operation");
+                    self.request = req(self.method, &self.host, &self.path);
+                    self.state = State::Checkout;
                 }
-                Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                    // As per the Rust `Future::poll` documentation, it's
-                    // expected that the Waker from the *most recent call*
-                    // should be scheduled to wake up. That means that everytime
-                    // we get a `WouldBlock` error, we need to make sure that
-                    // we store the most recent Waker.  The reason is that the
-                    // future could have moved to a different executor in between
-                    // calls, and we need to wake up the correct one (it won't
-                    // be possible to move futures like those in our example,
-                    // but we are playing by the same rules).
-                    reactor().set_waker(waker, self.id);
-                    // Since we put the stream in non-blocking mode,
-                    // the data is not ready yet, or there is more data, but
-                    // we haven't received it yet.
-                    return PollState::NotReady;
+                State::Checkout => {
+                    let key = self.target.to_string();
+                    match http_pool::checkout(&key, waker) {
+                        PollState::Ready((lease, Some(stream))) => {
+                            self.lease = Some(lease);
+                            self.stream = Some(stream);
+                            self.reused = true;
+                            self.state = State::Writing(0);
+                        }
+                        PollState::Ready((lease, None)) => {
+                            self.lease = Some(lease);
+                            self.state = self.connect();
+                        }
+                        PollState::NotReady => return PollState::NotReady,
+                    }
                 }
-                Err(e) if e.kind() == ErrorKind::Interrupted => {
-                    // reads can be interrupted by signals.
-                    // The operation was interrupted, try again
-                    continue;
+                State::Invalid(e) => {
+                    let e = e.take().expect("HttpGetFuture polled again");
+                    return PollState::Ready(Err(HttpError::Url(e)));
                 }
-                Err(e) => {
-                    // An other error occurred; simply panic
-                    panic!("Error reading from stream: {}", e);
+                State::Resolving(resolve) => match resolve.poll(waker) {
+                    PollState::Ready(Ok(addrs)) => {
+                        self.addrs = addrs.into();
+                        self.state = self.connect_next().unwrap();
+                    }
+                    PollState::Ready(Err(e)) => {
+                        return PollState::Ready(Err(HttpError::Resolve(e)));
+                    }
+                    PollState::NotReady => return PollState::NotReady,
+                },
+                State::Connecting(connect) => match connect.poll(waker) {
+                    PollState::Ready(Ok(stream)) => {
+                        self.lease.as_ref().unwrap().opened();
+                        self.stream = Some(stream);
+                        self.state = State::Writing(0);
+                    }
+                    // A name can resolve to several addresses, for example
+                    // `localhost` to both `::1` and `127.0.0.1`, and the
+                    // server may only listen on one of them.
+                    PollState::Ready(Err(e)) => match self.connect_next() {
+                        Some(next) => self.state = next,
+                        None => {
+                            return PollState::Ready(Err(HttpError::Connect(
+                                e,
+                            )));
+                        }
+                    },
+                    PollState::NotReady => return PollState::NotReady,
+                },
+                State::Writing(written) => {
+                    let stream = self.stream.as_mut().unwrap();
+                    let request = self.request.as_bytes();
+                    match io::poll_write_all(stream, waker, request, written) {
+                        PollState::Ready(Ok(())) => self.state = State::Reading,
+                        PollState::Ready(Err(_)) if self.is_stale() => {
+                            self.retry();
+                        }
+                        PollState::Ready(Err(e)) => {
+                            return PollState::Ready(Err(HttpError::Io(e)));
+                        }
+                        PollState::NotReady => return PollState::NotReady,
+                    }
                 }
+                State::Reading => match self.poll_response(waker) {
+                    PollState::Ready(Err(_)) if self.is_stale() => {
+                        self.retry();
+                    }
+                    PollState::Ready(result) => {
+                        return PollState::Ready(result);
+                    }
+                    PollState::NotReady => return PollState::NotReady,
+                },
             }
         }
     }
+
+    /// Connects to the next address we haven't tried yet.
+    fn connect_next(&mut self) -> Option<State> {
+        let addr = self.addrs.pop_front()?;
+        Some(State::Connecting(Connecting::Tcp(TcpStream::connect(addr))))
+    }
+}
+
+/// Implement the Future trait for our HttpGetFuture
+impl Future for HttpGetFuture {
+    type Output = Result<HttpResponse, HttpError>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        let result = match self.poll_request(waker) {
+            PollState::Ready(result) => result,
+            PollState::NotReady => return PollState::NotReady,
+        };
+        // Unless it went back to the pool, dropping the stream closes the
+        // connection and de-registers it from our `Poll` instance. Dropping
+        // the lease makes room for the next request to the same host.
+        self.stream = None;
+        self.lease = None;
+        PollState::Ready(result)
+    }
+}
+
+// Turns the response into text, or the error of a failed request into a
+// message, for `Http::get`.
+fn or_error_message(request: HttpGetFuture) -> impl Future<Output = String> {
+    let what = match request.state {
+        // There's no target to speak of.
+        State::Invalid(_) => format!("{} {}", request.method, request.path),
+        _ => format!(
+            "{} {} from {}",
+            request.method, request.path, request.target
+        ),
+    };
+    OrErrorMessage::new(request, what, |response: HttpResponse| {
+        response.to_string()
+    })
 }
 
 // -----------------------------------------------------------------------------
 
-pub fn get_req(path: &str) -> String {
+pub fn get_req(host: &str, path: &str) -> String {
+    req("GET", host, path)
+}
+
+pub fn req(method: &str, host: &str, path: &str) -> String {
     format!(
-        "GET {path} HTTP/1.1\r\n\
-             Host: localhost\r\n\
-             Connection: close\r\n\
+        "{method} {path} HTTP/1.1\r\n\
+             Host: {host}\r\n\
              \r\n"
     )
 }
@@ -0,0 +1,369 @@
+use std::{
+    cell::Cell,
+    io::{self, ErrorKind, Read, Write},
+    sync::Arc,
+};
+
+use mio::{Interest, event::Source};
+
+use crate::{
+    executor::{self, IoOwner, Waker},
+    future_with_waker::{Future, PollState},
+    reactor::{Direction, Reactor, ReadyEvent, reactor},
+};
+
+/// The link between an I/O source and the reactor: the reactor it's
+/// registered with and the ID its events are delivered under.
+///
+/// This is the bookkeeping every leaf future used to do by hand: allocate an
+/// ID, register the source, store the most recent Waker whenever an operation
+/// would block, and deregister the source when it's done. The reactor keeps
+/// track of the readiness of the source in each direction, so one task can
+/// wait to read from it while another waits to write to it.
+pub struct Registration {
+    reactor: Arc<Reactor>,
+    id: usize,
+    // The task that registered the source, which the executor counts the
+    // source against until it's deregistered. See `Executor::detect_stalls`.
+    owner: Cell<Option<IoOwner>>,
+}
+
+impl Registration {
+    /// Registers `source` with the running reactor.
+    pub fn new<S: Source + ?Sized>(
+        source: &mut S,
+        interest: Interest,
+    ) -> io::Result<Self> {
+        let reactor = reactor();
+        reactor.check_shutdown()?;
+        let id = reactor.next_id();
+        reactor.register(source, interest, id)?;
+        let owner = Cell::new(executor::note_io_registration());
+        Ok(Self { reactor, id, owner })
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Waits for the source to become ready in `direction`.
+    ///
+    /// If the reactor has been shut down, no event is ever going to arrive,
+    /// so we return an error instead.
+    pub fn poll_ready(
+        &self,
+        direction: Direction,
+        waker: &Waker,
+    ) -> PollState<io::Result<ReadyEvent>> {
+        if let Err(e) = self.reactor.check_shutdown() {
+            return PollState::Ready(Err(e));
+        }
+        match self.reactor.poll_ready(self.id, direction, waker) {
+            PollState::Ready(event) => PollState::Ready(Ok(event)),
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+
+    /// Forgets the readiness `event` reported. Call this when an operation
+    /// fails with `WouldBlock`.
+    pub fn clear_readiness(&self, event: ReadyEvent) {
+        self.reactor.clear_readiness(self.id, event);
+    }
+
+    /// Runs the non-blocking operation `op` until it completes, fails or
+    /// would block, as long as the source is ready in `direction`. If it
+    /// isn't, the Waker is stored with the reactor so that we're polled
+    /// again once the source is ready, and we return `NotReady`.
+    pub fn poll_io<R>(
+        &self,
+        direction: Direction,
+        waker: &Waker,
+        mut op: impl FnMut() -> io::Result<R>,
+    ) -> PollState<io::Result<R>> {
+        loop {
+            let event = match self.poll_ready(direction, waker) {
+                PollState::Ready(Ok(event)) => event,
+                PollState::Ready(Err(e)) => break PollState::Ready(Err(e)),
+                PollState::NotReady => break PollState::NotReady,
+            };
+            match op() {
+                // The source wasn't as ready as we thought. Once we've
+                // cleared its readiness, `poll_ready` stores our Waker (as
+                // per the Rust `Future::poll` documentation, the one from the
+                // *most recent call*), unless an event has arrived in the
+                // meantime, in which case we try again.
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    self.clear_readiness(event);
+                }
+                // The operation was interrupted by a signal, try again.
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                result => break PollState::Ready(result),
+            }
+        }
+    }
+
+    /// Deregisters `source`, which must be the source this registration was
+    /// created for.
+    pub fn deregister<S: Source + ?Sized>(
+        &self,
+        source: &mut S,
+    ) -> io::Result<()> {
+        if let Some(owner) = self.owner.take() {
+            executor::note_io_deregistration(owner);
+        }
+        self.reactor.deregister(source, self.id)
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// An I/O source registered with the reactor. The source is deregistered
+/// when this is dropped.
+pub struct PollEvented<S: Source> {
+    // Only `None` after `into_inner`.
+    io: Option<S>,
+    registration: Registration,
+}
+
+impl<S: Source> PollEvented<S> {
+    /// Registers `io` with the running reactor for the given interest.
+    pub fn new(mut io: S, interest: Interest) -> io::Result<Self> {
+        let registration = Registration::new(&mut io, interest)?;
+        Ok(Self {
+            io: Some(io),
+            registration,
+        })
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.io.as_ref().unwrap()
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        self.io.as_mut().unwrap()
+    }
+
+    pub fn registration(&self) -> &Registration {
+        &self.registration
+    }
+
+    /// Like `Registration::poll_io`, but hands the source to `op`.
+    pub fn poll_io<R>(
+        &mut self,
+        direction: Direction,
+        waker: &Waker,
+        mut op: impl FnMut(&mut S) -> io::Result<R>,
+    ) -> PollState<io::Result<R>> {
+        let io = self.io.as_mut().unwrap();
+        self.registration.poll_io(direction, waker, || op(io))
+    }
+
+    /// Deregisters the source and gives it back.
+    pub fn into_inner(mut self) -> io::Result<S> {
+        let mut io = self.io.take().unwrap();
+        self.registration.deregister(&mut io)?;
+        Ok(io)
+    }
+}
+
+impl<S: Source> Drop for PollEvented<S> {
+    fn drop(&mut self) {
+        if let Some(mut io) = self.io.take() {
+            let _ = self.registration.deregister(&mut io);
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// Reads bytes from a source without blocking.
+pub trait AsyncRead {
+    /// Reads some bytes into `buf` and returns how many, just like
+    /// `std::io::Read::read`. A return value of 0 means that we've reached
+    /// the end of the stream. If no data is available yet, returns
+    /// `NotReady` and arranges for `waker` to be woken once there is.
+    fn poll_read(
+        &mut self,
+        waker: &Waker,
+        buf: &mut [u8],
+    ) -> PollState<io::Result<usize>>;
+}
+
+/// Writes bytes to a sink without blocking.
+pub trait AsyncWrite {
+    /// Writes some bytes from `buf` and returns how many, just like
+    /// `std::io::Write::write`. If the sink can't take any more data yet,
+    /// returns `NotReady` and arranges for `waker` to be woken once it can.
+    fn poll_write(
+        &mut self,
+        waker: &Waker,
+        buf: &[u8],
+    ) -> PollState<io::Result<usize>>;
+
+    /// Makes sure everything written so far has reached its destination.
+    /// Sockets don't buffer anything themselves, so by default there's
+    /// nothing to do.
+    fn poll_flush(&mut self, _waker: &Waker) -> PollState<io::Result<()>> {
+        PollState::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncRead + ?Sized> AsyncRead for &mut T {
+    fn poll_read(
+        &mut self,
+        waker: &Waker,
+        buf: &mut [u8],
+    ) -> PollState<io::Result<usize>> {
+        (**self).poll_read(waker, buf)
+    }
+}
+
+impl<T: AsyncWrite + ?Sized> AsyncWrite for &mut T {
+    fn poll_write(
+        &mut self,
+        waker: &Waker,
+        buf: &[u8],
+    ) -> PollState<io::Result<usize>> {
+        (**self).poll_write(waker, buf)
+    }
+
+    fn poll_flush(&mut self, waker: &Waker) -> PollState<io::Result<()>> {
+        (**self).poll_flush(waker)
+    }
+}
+
+impl<S: Source + Read> AsyncRead for PollEvented<S> {
+    fn poll_read(
+        &mut self,
+        waker: &Waker,
+        buf: &mut [u8],
+    ) -> PollState<io::Result<usize>> {
+        self.poll_io(Direction::Read, waker, |io| io.read(buf))
+    }
+}
+
+impl<S: Source + Write> AsyncWrite for PollEvented<S> {
+    fn poll_write(
+        &mut self,
+        waker: &Waker,
+        buf: &[u8],
+    ) -> PollState<io::Result<usize>> {
+        self.poll_io(Direction::Write, waker, |io| io.write(buf))
+    }
+
+    fn poll_flush(&mut self, waker: &Waker) -> PollState<io::Result<()>> {
+        self.poll_io(Direction::Write, waker, |io| io.flush())
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// Reads from `reader` until the end of the stream, appending the data to
+/// `buf`. Resolves to the number of bytes read.
+pub fn read_to_end<'a, R: AsyncRead + ?Sized>(
+    reader: &'a mut R,
+    buf: &'a mut Vec<u8>,
+) -> ReadToEnd<'a, R> {
+    ReadToEnd {
+        reader,
+        buf,
+        read: 0,
+    }
+}
+
+/// Writes all of `buf` to `writer`.
+pub fn write_all<'a, W: AsyncWrite + ?Sized>(
+    writer: &'a mut W,
+    buf: &'a [u8],
+) -> WriteAll<'a, W> {
+    WriteAll {
+        writer,
+        buf,
+        written: 0,
+    }
+}
+
+/// The loop behind `read_to_end`, for leaf futures that keep the reader and
+/// the buffer in their own fields and can't hold on to a `ReadToEnd`, which
+/// borrows them. `read` keeps count of the bytes read across calls.
+///
+/// Every read that makes progress is charged against the task's poll
+/// budget, since a stream that always has more data would otherwise keep us
+/// going forever.
+pub fn poll_read_to_end<R: AsyncRead + ?Sized>(
+    reader: &mut R,
+    waker: &Waker,
+    buf: &mut Vec<u8>,
+    read: &mut usize,
+) -> PollState<io::Result<usize>> {
+    let mut chunk = [0; 4096];
+    loop {
+        match reader.poll_read(waker, &mut chunk) {
+            PollState::Ready(Ok(0)) => break PollState::Ready(Ok(*read)),
+            PollState::Ready(Ok(n)) => {
+                buf.extend_from_slice(&chunk[..n]);
+                *read += n;
+                // If we've used up our poll budget, give the other tasks a
+                // chance to run. `consume_budget` has already arranged for
+                // us to be polled again.
+                if !executor::consume_budget(waker) {
+                    break PollState::NotReady;
+                }
+            }
+            PollState::Ready(Err(e)) => break PollState::Ready(Err(e)),
+            PollState::NotReady => break PollState::NotReady,
+        }
+    }
+}
+
+/// The loop behind `write_all`. See `poll_read_to_end`. `written` keeps
+/// count of how much of `buf` has been written across calls.
+pub fn poll_write_all<W: AsyncWrite + ?Sized>(
+    writer: &mut W,
+    waker: &Waker,
+    buf: &[u8],
+    written: &mut usize,
+) -> PollState<io::Result<()>> {
+    while *written < buf.len() {
+        match writer.poll_write(waker, &buf[*written..]) {
+            PollState::Ready(Ok(0)) => {
+                let e = io::Error::from(ErrorKind::WriteZero);
+                return PollState::Ready(Err(e));
+            }
+            PollState::Ready(Ok(n)) => *written += n,
+            PollState::Ready(Err(e)) => return PollState::Ready(Err(e)),
+            PollState::NotReady => return PollState::NotReady,
+        }
+    }
+    PollState::Ready(Ok(()))
+}
+
+/// The future returned by `read_to_end`.
+pub struct ReadToEnd<'a, R: ?Sized> {
+    reader: &'a mut R,
+    buf: &'a mut Vec<u8>,
+    read: usize,
+}
+
+impl<R: AsyncRead + ?Sized> Future for ReadToEnd<'_, R> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        poll_read_to_end(self.reader, waker, self.buf, &mut self.read)
+    }
+}
+
+/// The future returned by `write_all`.
+pub struct WriteAll<'a, W: ?Sized> {
+    writer: &'a mut W,
+    buf: &'a [u8],
+    written: usize,
+}
+
+impl<W: AsyncWrite + ?Sized> Future for WriteAll<'_, W> {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        poll_write_all(self.writer, waker, self.buf, &mut self.written)
+    }
+}
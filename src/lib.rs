@@ -1,11 +1,25 @@
+pub mod blocking;
 pub mod executor;
 pub mod ffi;
+pub mod fs;
 pub mod future;
 pub mod future_with_waker;
 pub mod http;
 pub mod http_mio;
+pub mod http_pool;
+pub mod http_response;
 pub mod http_waker;
+pub mod io;
+pub mod logging;
+pub mod net;
 pub mod poll;
+#[cfg(unix)]
+pub mod process;
 pub mod reactor;
 pub mod runtime;
 pub mod runtime_two;
+pub mod sim;
+pub mod task_local;
+pub mod time;
+pub mod trace;
+pub mod url;
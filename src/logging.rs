@@ -0,0 +1,219 @@
+use std::{
+    fmt,
+    io::Write,
+    sync::{
+        RwLock,
+        atomic::{AtomicU8, Ordering},
+    },
+};
+
+use chrono::Local;
+
+/// The name of the environment variable that turns logging on, for example
+/// `RUNTIME_LOG=info` or `RUNTIME_LOG=warn,reactor=trace`.
+pub const ENV_VAR: &str = "RUNTIME_LOG";
+
+// The filter that decides which messages get written. It's read from
+// `ENV_VAR` the first time we log something, unless `set_filter` is called
+// before that.
+static FILTER: RwLock<Option<Filter>> = RwLock::new(None);
+
+// The most verbose level any module is enabled for, or 0 if logging is off
+// everywhere. This lets us skip the lock for the (by default) common case
+// of a message nobody wants to see. `UNINITIALISED` means we haven't looked
+// at the environment yet.
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(UNINITIALISED);
+const UNINITIALISED: u8 = u8::MAX;
+
+/// How important a message is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn parse(s: &str) -> Option<Option<Level>> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Some(None),
+            "error" => Some(Some(Level::Error)),
+            "warn" => Some(Some(Level::Warn)),
+            "info" => Some(Some(Level::Info)),
+            "debug" => Some(Some(Level::Debug)),
+            "trace" => Some(Some(Level::Trace)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+        f.pad(s)
+    }
+}
+
+/// Decides which messages get written, by module.
+///
+/// A filter is written as a comma separated list of directives. A directive
+/// is either a level (`off`, `error`, `warn`, `info`, `debug` or `trace`),
+/// which applies to every module, or `module=level`, which applies to the
+/// module and everything below it. Module names can leave out the name of the
+/// crate, so `reactor=trace` and `learn_async_rust::reactor=trace` mean the
+/// same thing. If several directives match a module, the most specific wins.
+#[derive(Debug, Default, Clone)]
+pub struct Filter {
+    default: Option<Level>,
+    modules: Vec<(String, Option<Level>)>,
+}
+
+impl Filter {
+    /// Parses a filter, ignoring any directives that don't make sense.
+    pub fn parse(spec: &str) -> Self {
+        let mut filter = Filter::default();
+        for directive in spec.split(',').filter(|d| !d.trim().is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    if let Some(level) = Level::parse(level) {
+                        filter.modules.push((module.trim().to_string(), level));
+                    }
+                }
+                None => {
+                    if let Some(level) = Level::parse(directive) {
+                        filter.default = level;
+                    }
+                }
+            }
+        }
+        // Longest module name first, so that the first match is the most
+        // specific one.
+        filter
+            .modules
+            .sort_by_key(|(m, _)| std::cmp::Reverse(m.len()));
+        filter
+    }
+
+    /// Returns `true` if messages at `level` from `module` should be written.
+    pub fn enabled(&self, level: Level, module: &str) -> bool {
+        let short = module
+            .split_once("::")
+            .map(|(_, rest)| rest)
+            .unwrap_or(module);
+        let max = self
+            .modules
+            .iter()
+            .find(|(m, _)| is_within(module, m) || is_within(short, m))
+            .map(|(_, l)| *l)
+            .unwrap_or(self.default);
+        max.is_some_and(|max| level <= max)
+    }
+
+    fn max_level(&self) -> u8 {
+        self.modules
+            .iter()
+            .map(|(_, l)| *l)
+            .chain([self.default])
+            .map(|l| l.map_or(0, |l| l as u8))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Returns `true` if `module` is `parent` or a module below it.
+fn is_within(module: &str, parent: &str) -> bool {
+    module == parent
+        || module
+            .strip_prefix(parent)
+            .is_some_and(|rest| rest.starts_with("::"))
+}
+
+/// Replaces the filter, for example from `runtime_two::Builder::log_filter`.
+/// This takes precedence over the environment variable.
+pub fn set_filter(filter: Filter) {
+    MAX_LEVEL.store(filter.max_level(), Ordering::Relaxed);
+    *FILTER.write().unwrap() = Some(filter);
+}
+
+/// Returns `true` if messages at `level` from `module` should be written.
+pub fn enabled(level: Level, module: &str) -> bool {
+    let mut max = MAX_LEVEL.load(Ordering::Relaxed);
+    if max == UNINITIALISED {
+        init_from_env();
+        max = MAX_LEVEL.load(Ordering::Relaxed);
+    }
+    if level as u8 > max {
+        return false;
+    }
+    FILTER
+        .read()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|f| f.enabled(level, module))
+}
+
+fn init_from_env() {
+    let mut filter = FILTER.write().unwrap();
+    // Someone might have beaten us to it.
+    if filter.is_none() {
+        let f = Filter::parse(&std::env::var(ENV_VAR).unwrap_or_default());
+        MAX_LEVEL.store(f.max_level(), Ordering::Relaxed);
+        *filter = Some(f);
+    }
+}
+
+/// Writes a message to stderr. Use the macros instead of calling this
+/// directly; they check the filter first so that the message isn't even
+/// formatted if nobody wants to see it.
+pub fn write(level: Level, module: &str, args: fmt::Arguments<'_>) {
+    let now = Local::now().format("%H:%M:%S%.6f");
+    let thread = std::thread::current();
+    let thread = thread.name().unwrap_or("unnamed");
+    let _ = writeln!(
+        std::io::stderr().lock(),
+        "{now} {level:<5} [{thread}] {module}: {args}"
+    );
+}
+
+// -----------------------------------------------------------------------------
+
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::logging::enabled($level, module_path!()) {
+            $crate::logging::write($level, module_path!(), format_args!($($arg)+));
+        }
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::logging::log!($crate::logging::Level::Error, $($arg)+) };
+}
+
+// Named `warning` here, because a macro called `warn` can't be imported
+// without clashing with the built-in `#[warn]` attribute. It's exported as
+// `warn` below.
+macro_rules! warning {
+    ($($arg:tt)+) => { $crate::logging::log!($crate::logging::Level::Warn, $($arg)+) };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::logging::log!($crate::logging::Level::Info, $($arg)+) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::logging::log!($crate::logging::Level::Debug, $($arg)+) };
+}
+
+macro_rules! trace {
+    ($($arg:tt)+) => { $crate::logging::log!($crate::logging::Level::Trace, $($arg)+) };
+}
+
+pub(crate) use {debug, error, info, log, trace, warning as warn};
@@ -0,0 +1,555 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{Shutdown, SocketAddr},
+};
+#[cfg(unix)]
+use std::{
+    os::unix::net::SocketAddr as UnixSocketAddr,
+    path::{Path, PathBuf},
+};
+
+use mio::Interest;
+
+use crate::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+    io::{AsyncRead, AsyncWrite, PollEvented, Registration},
+    reactor::Direction,
+};
+
+/// A TCP connection, registered with the reactor.
+///
+/// Reading and writing go through `AsyncRead` and `AsyncWrite`, so the
+/// helpers in `io` (`read_to_end`, `write_all`) work with it.
+pub struct TcpStream {
+    io: PollEvented<mio::net::TcpStream>,
+}
+
+impl TcpStream {
+    /// Returns a future that connects to `addr`. The connection is made in
+    /// the background: the future resolves once it's established, or fails.
+    pub fn connect(addr: SocketAddr) -> Connect {
+        Connect { addr, stream: None }
+    }
+
+    /// Registers a connected standard library stream with the reactor.
+    pub fn from_std(stream: std::net::TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Self::from_mio(mio::net::TcpStream::from_std(stream))
+    }
+
+    pub(crate) fn from_mio(stream: mio::net::TcpStream) -> io::Result<Self> {
+        let interest = Interest::READABLE | Interest::WRITABLE;
+        Ok(Self {
+            io: PollEvented::new(stream, interest)?,
+        })
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.io.get_ref().peer_addr()
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.io.get_ref().local_addr()
+    }
+
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.io.get_ref().set_nodelay(nodelay)
+    }
+
+    /// Shuts down the read half, the write half or both halves of the
+    /// connection. This doesn't block, so there's no need for a future.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.io.get_ref().shutdown(how)
+    }
+}
+
+impl AsyncRead for TcpStream {
+    fn poll_read(
+        &mut self,
+        waker: &Waker,
+        buf: &mut [u8],
+    ) -> PollState<io::Result<usize>> {
+        self.io.poll_read(waker, buf)
+    }
+}
+
+impl AsyncWrite for TcpStream {
+    fn poll_write(
+        &mut self,
+        waker: &Waker,
+        buf: &[u8],
+    ) -> PollState<io::Result<usize>> {
+        self.io.poll_write(waker, buf)
+    }
+
+    fn poll_flush(&mut self, waker: &Waker) -> PollState<io::Result<()>> {
+        self.io.poll_flush(waker)
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// A TCP socket that listens for connections, registered with the reactor.
+pub struct TcpListener {
+    io: PollEvented<mio::net::TcpListener>,
+}
+
+impl TcpListener {
+    /// Binds a new listener to `addr`. Binding doesn't block, so this
+    /// returns the listener straight away. The reactor has to be running.
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let listener = mio::net::TcpListener::bind(addr)?;
+        Ok(Self {
+            io: PollEvented::new(listener, Interest::READABLE)?,
+        })
+    }
+
+    /// Registers a standard library listener with the reactor.
+    pub fn from_std(listener: std::net::TcpListener) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        let listener = mio::net::TcpListener::from_std(listener);
+        Ok(Self {
+            io: PollEvented::new(listener, Interest::READABLE)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.io.get_ref().local_addr()
+    }
+
+    /// Returns a future that resolves to the next connection.
+    pub fn accept(&mut self) -> Accept<'_> {
+        Accept { listener: self }
+    }
+
+    /// Accepts the next connection, if there is one. If there isn't, returns
+    /// `NotReady` and arranges for `waker` to be woken once there is.
+    ///
+    /// A server that accepts connections in a loop could keep going forever
+    /// under load, so every connection is charged against the task's poll
+    /// budget. We check the budget before we accept, so that we never have to
+    /// drop a connection we've already accepted.
+    pub fn poll_accept(
+        &mut self,
+        waker: &Waker,
+    ) -> PollState<io::Result<(TcpStream, SocketAddr)>> {
+        if !executor::consume_budget(waker) {
+            return PollState::NotReady;
+        }
+        match self.io.poll_io(Direction::Read, waker, |l| l.accept()) {
+            PollState::Ready(Ok((stream, addr))) => {
+                PollState::Ready(TcpStream::from_mio(stream).map(|s| (s, addr)))
+            }
+            PollState::Ready(Err(e)) => PollState::Ready(Err(e)),
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+/// The future returned by `TcpListener::accept`.
+pub struct Accept<'a> {
+    listener: &'a mut TcpListener,
+}
+
+impl Future for Accept<'_> {
+    type Output = io::Result<(TcpStream, SocketAddr)>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        self.listener.poll_accept(waker)
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// A UDP socket, registered with the reactor.
+///
+/// All the methods take `&self`, so one task can wait to receive a datagram
+/// while another one sends on the same socket.
+pub struct UdpSocket {
+    io: PollEvented<mio::net::UdpSocket>,
+}
+
+impl UdpSocket {
+    /// Binds a new socket to `addr`. The reactor has to be running.
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = mio::net::UdpSocket::bind(addr)?;
+        let interest = Interest::READABLE | Interest::WRITABLE;
+        Ok(Self {
+            io: PollEvented::new(socket, interest)?,
+        })
+    }
+
+    /// Registers a standard library socket with the reactor.
+    pub fn from_std(socket: std::net::UdpSocket) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        let socket = mio::net::UdpSocket::from_std(socket);
+        let interest = Interest::READABLE | Interest::WRITABLE;
+        Ok(Self {
+            io: PollEvented::new(socket, interest)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.io.get_ref().local_addr()
+    }
+
+    /// Returns a future that sends `buf` to `target` as a single datagram,
+    /// and resolves to the number of bytes sent.
+    pub fn send_to<'a>(
+        &'a self,
+        buf: &'a [u8],
+        target: SocketAddr,
+    ) -> SendTo<'a> {
+        SendTo {
+            socket: self,
+            buf,
+            target,
+        }
+    }
+
+    /// Returns a future that receives a single datagram into `buf`, and
+    /// resolves to its size and where it came from. If the datagram doesn't
+    /// fit into `buf`, the rest of it is lost.
+    pub fn recv_from<'a>(&'a self, buf: &'a mut [u8]) -> RecvFrom<'a> {
+        RecvFrom { socket: self, buf }
+    }
+
+    /// Sends `buf` to `target`, unless the socket's send buffer is full, in
+    /// which case this returns `NotReady` and arranges for `waker` to be
+    /// woken once there's room.
+    pub fn poll_send_to(
+        &self,
+        waker: &Waker,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> PollState<io::Result<usize>> {
+        let socket = self.io.get_ref();
+        self.io
+            .registration()
+            .poll_io(Direction::Write, waker, || socket.send_to(buf, target))
+    }
+
+    /// Receives a datagram, if one has arrived. If none has, returns
+    /// `NotReady` and arranges for `waker` to be woken once one does.
+    pub fn poll_recv_from(
+        &self,
+        waker: &Waker,
+        buf: &mut [u8],
+    ) -> PollState<io::Result<(usize, SocketAddr)>> {
+        let socket = self.io.get_ref();
+        self.io
+            .registration()
+            .poll_io(Direction::Read, waker, || socket.recv_from(buf))
+    }
+}
+
+/// The future returned by `UdpSocket::send_to`.
+pub struct SendTo<'a> {
+    socket: &'a UdpSocket,
+    buf: &'a [u8],
+    target: SocketAddr,
+}
+
+impl Future for SendTo<'_> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        self.socket.poll_send_to(waker, self.buf, self.target)
+    }
+}
+
+/// The future returned by `UdpSocket::recv_from`.
+pub struct RecvFrom<'a> {
+    socket: &'a UdpSocket,
+    buf: &'a mut [u8],
+}
+
+impl Future for RecvFrom<'_> {
+    type Output = io::Result<(usize, SocketAddr)>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        self.socket.poll_recv_from(waker, self.buf)
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// The future returned by `TcpStream::connect`.
+pub struct Connect {
+    addr: SocketAddr,
+    // Set on the first poll, so that the future can be created before the
+    // reactor is started.
+    stream: Option<TcpStream>,
+}
+
+impl Future for Connect {
+    type Output = io::Result<TcpStream>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        // Start connecting. Since the stream is in non-blocking mode, this
+        // returns straight away, and the reactor tells us once the stream
+        // becomes writable that the connection is established (or that it
+        // failed).
+        if self.stream.is_none() {
+            let stream = mio::net::TcpStream::connect(self.addr)
+                .and_then(TcpStream::from_mio);
+            match stream {
+                Ok(stream) => self.stream = Some(stream),
+                Err(e) => return PollState::Ready(Err(e)),
+            }
+        }
+
+        let stream = self.stream.as_ref().unwrap();
+        let io = stream.io.get_ref();
+        match poll_connected(stream.io.registration(), waker, || {
+            is_connected(io.take_error(), io.peer_addr())
+        }) {
+            PollState::Ready(Ok(())) => {}
+            PollState::Ready(Err(e)) => return PollState::Ready(Err(e)),
+            PollState::NotReady => return PollState::NotReady,
+        }
+        PollState::Ready(Ok(self.stream.take().unwrap()))
+    }
+}
+
+/// Waits for a non-blocking connect to finish. `connected` checks whether it
+/// has: it returns `Ok(false)` while the connection is still in progress.
+fn poll_connected(
+    registration: &Registration,
+    waker: &Waker,
+    mut connected: impl FnMut() -> io::Result<bool>,
+) -> PollState<io::Result<()>> {
+    loop {
+        let event = match registration.poll_ready(Direction::Write, waker) {
+            PollState::Ready(Ok(event)) => event,
+            PollState::Ready(Err(e)) => return PollState::Ready(Err(e)),
+            PollState::NotReady => return PollState::NotReady,
+        };
+        match connected() {
+            Ok(true) => return PollState::Ready(Ok(())),
+            // Not connected yet, so the readiness we got must have been left
+            // over from before.
+            Ok(false) => {
+                if event.readiness.is_error()
+                    || event.readiness.is_write_closed()
+                {
+                    let e = io::Error::from(ErrorKind::NotConnected);
+                    return PollState::Ready(Err(e));
+                }
+                registration.clear_readiness(event);
+            }
+            Err(e) => return PollState::Ready(Err(e)),
+        }
+    }
+}
+
+/// Works out whether a socket is connected from its `SO_ERROR` and the result
+/// of asking for its peer's address.
+fn is_connected<A>(
+    error: io::Result<Option<io::Error>>,
+    peer_addr: io::Result<A>,
+) -> io::Result<bool> {
+    // If the connection failed, the reason is waiting for us in `SO_ERROR`.
+    match error {
+        Ok(None) => {}
+        Ok(Some(e)) | Err(e) => return Err(e),
+    }
+    match peer_addr {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotConnected => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// A connection over a Unix domain socket, registered with the reactor. It
+/// works just like a `TcpStream`, but is addressed by a path on the local
+/// file system instead of an IP address and a port.
+#[cfg(unix)]
+pub struct UnixStream {
+    io: PollEvented<mio::net::UnixStream>,
+}
+
+#[cfg(unix)]
+impl UnixStream {
+    /// Returns a future that connects to the socket at `path`.
+    pub fn connect(path: impl Into<PathBuf>) -> UnixConnect {
+        UnixConnect {
+            path: path.into(),
+            stream: None,
+        }
+    }
+
+    /// Registers a connected standard library stream with the reactor.
+    pub fn from_std(
+        stream: std::os::unix::net::UnixStream,
+    ) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Self::from_mio(mio::net::UnixStream::from_std(stream))
+    }
+
+    fn from_mio(stream: mio::net::UnixStream) -> io::Result<Self> {
+        let interest = Interest::READABLE | Interest::WRITABLE;
+        Ok(Self {
+            io: PollEvented::new(stream, interest)?,
+        })
+    }
+
+    /// Returns a pair of streams that are connected to each other.
+    pub fn pair() -> io::Result<(Self, Self)> {
+        let (a, b) = mio::net::UnixStream::pair()?;
+        Ok((Self::from_mio(a)?, Self::from_mio(b)?))
+    }
+
+    pub fn peer_addr(&self) -> io::Result<UnixSocketAddr> {
+        self.io.get_ref().peer_addr()
+    }
+
+    pub fn local_addr(&self) -> io::Result<UnixSocketAddr> {
+        self.io.get_ref().local_addr()
+    }
+
+    /// Shuts down the read half, the write half or both halves of the
+    /// connection.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.io.get_ref().shutdown(how)
+    }
+}
+
+#[cfg(unix)]
+impl AsyncRead for UnixStream {
+    fn poll_read(
+        &mut self,
+        waker: &Waker,
+        buf: &mut [u8],
+    ) -> PollState<io::Result<usize>> {
+        self.io.poll_read(waker, buf)
+    }
+}
+
+#[cfg(unix)]
+impl AsyncWrite for UnixStream {
+    fn poll_write(
+        &mut self,
+        waker: &Waker,
+        buf: &[u8],
+    ) -> PollState<io::Result<usize>> {
+        self.io.poll_write(waker, buf)
+    }
+
+    fn poll_flush(&mut self, waker: &Waker) -> PollState<io::Result<()>> {
+        self.io.poll_flush(waker)
+    }
+}
+
+/// The future returned by `UnixStream::connect`.
+#[cfg(unix)]
+pub struct UnixConnect {
+    path: PathBuf,
+    // Set on the first poll, like in `Connect`.
+    stream: Option<UnixStream>,
+}
+
+#[cfg(unix)]
+impl Future for UnixConnect {
+    type Output = io::Result<UnixStream>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        // Connecting to a Unix socket usually completes straight away, but
+        // we can't count on it, so we wait for the stream to become writable
+        // just like we do for TCP.
+        if self.stream.is_none() {
+            let stream = mio::net::UnixStream::connect(&self.path)
+                .and_then(UnixStream::from_mio);
+            match stream {
+                Ok(stream) => self.stream = Some(stream),
+                Err(e) => return PollState::Ready(Err(e)),
+            }
+        }
+
+        let stream = self.stream.as_ref().unwrap();
+        let io = stream.io.get_ref();
+        match poll_connected(stream.io.registration(), waker, || {
+            is_connected(io.take_error(), io.peer_addr())
+        }) {
+            PollState::Ready(Ok(())) => {}
+            PollState::Ready(Err(e)) => return PollState::Ready(Err(e)),
+            PollState::NotReady => return PollState::NotReady,
+        }
+        PollState::Ready(Ok(self.stream.take().unwrap()))
+    }
+}
+
+/// A Unix domain socket that listens for connections, registered with the
+/// reactor.
+#[cfg(unix)]
+pub struct UnixListener {
+    io: PollEvented<mio::net::UnixListener>,
+}
+
+#[cfg(unix)]
+impl UnixListener {
+    /// Binds a new listener to `path`. Fails if something already exists at
+    /// `path`, so remove a socket left over from an earlier run first.
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        let listener = mio::net::UnixListener::bind(path)?;
+        Ok(Self {
+            io: PollEvented::new(listener, Interest::READABLE)?,
+        })
+    }
+
+    /// Registers a standard library listener with the reactor.
+    pub fn from_std(
+        listener: std::os::unix::net::UnixListener,
+    ) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        let listener = mio::net::UnixListener::from_std(listener);
+        Ok(Self {
+            io: PollEvented::new(listener, Interest::READABLE)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<UnixSocketAddr> {
+        self.io.get_ref().local_addr()
+    }
+
+    /// Returns a future that resolves to the next connection.
+    pub fn accept(&mut self) -> UnixAccept<'_> {
+        UnixAccept { listener: self }
+    }
+
+    /// Accepts the next connection, if there is one. Like
+    /// `TcpListener::poll_accept`, this is charged against the poll budget.
+    pub fn poll_accept(
+        &mut self,
+        waker: &Waker,
+    ) -> PollState<io::Result<(UnixStream, UnixSocketAddr)>> {
+        if !executor::consume_budget(waker) {
+            return PollState::NotReady;
+        }
+        match self.io.poll_io(Direction::Read, waker, |l| l.accept()) {
+            PollState::Ready(Ok((stream, addr))) => PollState::Ready(
+                UnixStream::from_mio(stream).map(|s| (s, addr)),
+            ),
+            PollState::Ready(Err(e)) => PollState::Ready(Err(e)),
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+/// The future returned by `UnixListener::accept`.
+#[cfg(unix)]
+pub struct UnixAccept<'a> {
+    listener: &'a mut UnixListener,
+}
+
+#[cfg(unix)]
+impl Future for UnixAccept<'_> {
+    type Output = io::Result<(UnixStream, UnixSocketAddr)>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        self.listener.poll_accept(waker)
+    }
+}
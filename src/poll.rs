@@ -2,7 +2,7 @@
 mod poll_impl {
     use std::{io, net::TcpStream, os::fd::AsRawFd};
 
-    use crate::ffi;
+    use crate::{ffi, logging};
 
     type Events = Vec<ffi::Event>;
 
@@ -102,7 +102,7 @@ mod poll_impl {
             let res = unsafe { ffi::close(self.raw_fd) };
             if res < 0 {
                 let err = io::Error::last_os_error();
-                eprintln!("Error closing epoll fd: {}", err);
+                logging::error!("Error closing epoll fd: {}", err);
             }
         }
     }
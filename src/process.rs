@@ -0,0 +1,520 @@
+//! Child processes, with their pipes and their exit driven by the reactor.
+//!
+//! The pipes to a child's stdin, stdout and stderr are registered with the
+//! reactor as non-blocking sources, so reading its output is just like
+//! reading from a socket. Waiting for the child to exit works the same way on
+//! Linux: `pidfd_open` gives us a file descriptor that becomes readable once
+//! the child has exited. On other systems (or old kernels without pidfds) a
+//! helper thread waits for the child instead, and wakes the task.
+use std::{
+    ffi::OsStr,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::Path,
+    process::{self, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use mio::{
+    Interest, Registry, Token,
+    event::Source,
+    unix::{SourceFd, pipe},
+};
+
+use crate::{
+    executor::Waker,
+    ffi,
+    future_with_waker::{Future, OrErrorMessage, PollState},
+    io::{AsyncRead, AsyncWrite, PollEvented},
+    logging,
+    reactor::{self, Direction},
+};
+
+/// Runs `script` with `sh -c`, and resolves to what it printed to stdout.
+///
+/// If the script can't be started or fails, it resolves to a description of
+/// what went wrong instead (see `future_with_waker::OrErrorMessage`). Use
+/// `Command::output` to tell the two apart.
+pub fn shell(script: impl AsRef<OsStr>) -> impl Future<Output = String> {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(&script);
+    let what = format!("Running `{}`", script.as_ref().to_string_lossy());
+    OrErrorMessage::new(command.output(), what, |output: process::Output| {
+        let text = |bytes| String::from_utf8_lossy(bytes).into_owned();
+        if output.status.success() {
+            text(&output.stdout)
+        } else {
+            let stderr = text(&output.stderr);
+            format!("Error: {}: {}", output.status, stderr.trim_end())
+        }
+    })
+}
+
+/// Builds a child process, like `std::process::Command`, and spawns it with
+/// its pipes registered with the reactor.
+pub struct Command {
+    inner: process::Command,
+}
+
+impl Command {
+    pub fn new(program: impl AsRef<OsStr>) -> Self {
+        Self {
+            inner: process::Command::new(program),
+        }
+    }
+
+    pub fn arg(&mut self, arg: impl AsRef<OsStr>) -> &mut Self {
+        self.inner.arg(arg);
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.inner.args(args);
+        self
+    }
+
+    pub fn env(
+        &mut self,
+        key: impl AsRef<OsStr>,
+        val: impl AsRef<OsStr>,
+    ) -> &mut Self {
+        self.inner.env(key, val);
+        self
+    }
+
+    pub fn current_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.inner.current_dir(dir);
+        self
+    }
+
+    /// Sets up the child's stdin. Pass `Stdio::piped()` to write to it
+    /// through `Child::stdin`.
+    pub fn stdin(&mut self, cfg: Stdio) -> &mut Self {
+        self.inner.stdin(cfg);
+        self
+    }
+
+    pub fn stdout(&mut self, cfg: Stdio) -> &mut Self {
+        self.inner.stdout(cfg);
+        self
+    }
+
+    pub fn stderr(&mut self, cfg: Stdio) -> &mut Self {
+        self.inner.stderr(cfg);
+        self
+    }
+
+    /// Starts the child. The reactor has to be running.
+    pub fn spawn(&mut self) -> io::Result<Child> {
+        Child::new(self.inner.spawn()?)
+    }
+
+    /// Starts the child with its stdout and stderr piped, and returns a
+    /// future that collects both of them and waits for it to exit. Its stdin
+    /// is closed.
+    pub fn output(&mut self) -> OutputFuture {
+        let child = self
+            .inner
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .and_then(Child::new);
+        OutputFuture {
+            child: child.map_err(Some),
+            stdout: (vec![], 0),
+            stderr: (vec![], 0),
+            status: None,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// A running (or exited) child process.
+///
+/// Just like with `std::process::Child`, dropping this doesn't kill the child
+/// or wait for it.
+pub struct Child {
+    child: process::Child,
+    exit: Exit,
+    pub stdin: Option<ChildStdin>,
+    pub stdout: Option<ChildStdout>,
+    pub stderr: Option<ChildStderr>,
+}
+
+// How we find out that the child has exited.
+enum Exit {
+    Pidfd(PollEvented<PidFd>),
+    Thread(Arc<Mutex<ExitSlot>>),
+    Exited(ExitStatus),
+}
+
+impl Exit {
+    // Sets up waiting for the process `pid` to exit, with a pidfd if we can,
+    // and with a thread if we can't.
+    fn watch(pid: u32) -> io::Result<Self> {
+        let pidfd = PidFd::open(pid)
+            .and_then(|pidfd| PollEvented::new(pidfd, Interest::READABLE));
+        match pidfd {
+            Ok(pidfd) => Ok(Exit::Pidfd(pidfd)),
+            Err(e) => {
+                logging::debug!("No pidfd, waiting in a thread: {e}");
+                Ok(Exit::Thread(wait_in_thread(pid)?))
+            }
+        }
+    }
+}
+
+// Shared with the thread that waits for the child to exit.
+#[derive(Default)]
+struct ExitSlot {
+    exited: bool,
+    waker: Option<Waker>,
+}
+
+impl Child {
+    fn new(mut child: process::Child) -> io::Result<Self> {
+        let exit = Exit::watch(child.id());
+        let stdin = child.stdin.take().map(ChildStdin::new).transpose();
+        let stdout = child.stdout.take().map(ChildStdout::new).transpose();
+        let stderr = child.stderr.take().map(ChildStderr::new).transpose();
+        match (exit, stdin, stdout, stderr) {
+            (Ok(exit), Ok(stdin), Ok(stdout), Ok(stderr)) => Ok(Self {
+                child,
+                exit,
+                stdin,
+                stdout,
+                stderr,
+            }),
+            (Err(e), ..) | (_, Err(e), ..) | (.., Err(e), _) | (.., Err(e)) => {
+                // Nobody is ever going to wait for the child, so we don't
+                // leave it running, and reap it before we return.
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(e)
+            }
+        }
+    }
+
+    /// The OS-assigned process ID of the child.
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Sends the child `SIGKILL`. This doesn't wait for it to exit, so wait
+    /// for it afterwards to find out when it has.
+    pub fn kill(&mut self) -> io::Result<()> {
+        self.child.kill()
+    }
+
+    /// Returns a future that waits for the child to exit and resolves to its
+    /// exit status.
+    ///
+    /// Its stdin is closed first, so that a child that reads until the end
+    /// of its input doesn't wait for us forever.
+    pub fn wait(&mut self) -> Wait<'_> {
+        self.stdin = None;
+        Wait { child: self }
+    }
+
+    /// Returns the child's exit status if it has exited. If it hasn't,
+    /// returns `NotReady` and arranges for `waker` to be woken once it has.
+    pub fn poll_wait(
+        &mut self,
+        waker: &Waker,
+    ) -> PollState<io::Result<ExitStatus>> {
+        loop {
+            match &self.exit {
+                Exit::Exited(status) => return PollState::Ready(Ok(*status)),
+                Exit::Pidfd(pidfd) => {
+                    let registration = pidfd.registration();
+                    let event =
+                        match registration.poll_ready(Direction::Read, waker) {
+                            PollState::Ready(Ok(event)) => event,
+                            PollState::Ready(Err(e)) => {
+                                return PollState::Ready(Err(e));
+                            }
+                            PollState::NotReady => return PollState::NotReady,
+                        };
+                    match self.child.try_wait() {
+                        Ok(Some(status)) => self.exit = Exit::Exited(status),
+                        // The readiness was left over from before.
+                        Ok(None) => registration.clear_readiness(event),
+                        Err(e) => return PollState::Ready(Err(e)),
+                    }
+                }
+                Exit::Thread(slot) => {
+                    let mut slot = slot.lock().unwrap();
+                    if !slot.exited {
+                        slot.waker = Some(waker.clone());
+                        return PollState::NotReady;
+                    }
+                    drop(slot);
+                    // The thread only waits for the child to exit. Reaping
+                    // it is up to us, so that `kill` can never hit another
+                    // process that has been given the same ID.
+                    match self.child.wait() {
+                        Ok(status) => self.exit = Exit::Exited(status),
+                        Err(e) => return PollState::Ready(Err(e)),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The future returned by `Child::wait`.
+pub struct Wait<'a> {
+    child: &'a mut Child,
+}
+
+impl Future for Wait<'_> {
+    type Output = io::Result<ExitStatus>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        self.child.poll_wait(waker)
+    }
+}
+
+/// The future returned by `Command::output`.
+pub struct OutputFuture {
+    // Holds the error if the child couldn't be started, until we've
+    // returned it.
+    child: Result<Child, Option<io::Error>>,
+    // What the child has printed so far, and how much of it.
+    stdout: (Vec<u8>, usize),
+    stderr: (Vec<u8>, usize),
+    status: Option<ExitStatus>,
+}
+
+impl Future for OutputFuture {
+    type Output = io::Result<process::Output>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        let child = match &mut self.child {
+            Ok(child) => child,
+            Err(e) => {
+                let e =
+                    e.take().expect("OutputFuture polled after it resolved");
+                return PollState::Ready(Err(e));
+            }
+        };
+
+        // Read both pipes at the same time: if we read them one after the
+        // other, a child that fills up the pipe we're not reading would
+        // wait for us while we wait for it.
+        if let Some(stdout) = &mut child.stdout {
+            let (buf, read) = &mut self.stdout;
+            match crate::io::poll_read_to_end(stdout, waker, buf, read) {
+                PollState::Ready(Ok(_)) => child.stdout = None,
+                PollState::Ready(Err(e)) => return PollState::Ready(Err(e)),
+                PollState::NotReady => {}
+            }
+        }
+        if let Some(stderr) = &mut child.stderr {
+            let (buf, read) = &mut self.stderr;
+            match crate::io::poll_read_to_end(stderr, waker, buf, read) {
+                PollState::Ready(Ok(_)) => child.stderr = None,
+                PollState::Ready(Err(e)) => return PollState::Ready(Err(e)),
+                PollState::NotReady => {}
+            }
+        }
+        if self.status.is_none() {
+            match child.poll_wait(waker) {
+                PollState::Ready(Ok(status)) => self.status = Some(status),
+                PollState::Ready(Err(e)) => return PollState::Ready(Err(e)),
+                PollState::NotReady => {}
+            }
+        }
+
+        match self.status {
+            Some(status)
+                if child.stdout.is_none() && child.stderr.is_none() =>
+            {
+                PollState::Ready(Ok(process::Output {
+                    status,
+                    stdout: std::mem::take(&mut self.stdout.0),
+                    stderr: std::mem::take(&mut self.stderr.0),
+                }))
+            }
+            _ => PollState::NotReady,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// The write end of the pipe to a child's stdin.
+pub struct ChildStdin {
+    io: PollEvented<pipe::Sender>,
+}
+
+impl ChildStdin {
+    fn new(stdin: process::ChildStdin) -> io::Result<Self> {
+        let sender = pipe::Sender::from(stdin);
+        sender.set_nonblocking(true)?;
+        Ok(Self {
+            io: PollEvented::new(sender, Interest::WRITABLE)?,
+        })
+    }
+}
+
+impl AsyncWrite for ChildStdin {
+    fn poll_write(
+        &mut self,
+        waker: &Waker,
+        buf: &[u8],
+    ) -> PollState<io::Result<usize>> {
+        self.io.poll_write(waker, buf)
+    }
+}
+
+/// The read end of the pipe from a child's stdout.
+pub struct ChildStdout {
+    io: PollEvented<pipe::Receiver>,
+}
+
+impl ChildStdout {
+    fn new(stdout: process::ChildStdout) -> io::Result<Self> {
+        let receiver = pipe::Receiver::from(stdout);
+        receiver.set_nonblocking(true)?;
+        Ok(Self {
+            io: PollEvented::new(receiver, Interest::READABLE)?,
+        })
+    }
+}
+
+impl AsyncRead for ChildStdout {
+    fn poll_read(
+        &mut self,
+        waker: &Waker,
+        buf: &mut [u8],
+    ) -> PollState<io::Result<usize>> {
+        self.io.poll_read(waker, buf)
+    }
+}
+
+/// The read end of the pipe from a child's stderr.
+pub struct ChildStderr {
+    io: PollEvented<pipe::Receiver>,
+}
+
+impl ChildStderr {
+    fn new(stderr: process::ChildStderr) -> io::Result<Self> {
+        let receiver = pipe::Receiver::from(stderr);
+        receiver.set_nonblocking(true)?;
+        Ok(Self {
+            io: PollEvented::new(receiver, Interest::READABLE)?,
+        })
+    }
+}
+
+impl AsyncRead for ChildStderr {
+    fn poll_read(
+        &mut self,
+        waker: &Waker,
+        buf: &mut [u8],
+    ) -> PollState<io::Result<usize>> {
+        self.io.poll_read(waker, buf)
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// A file descriptor that refers to a process, and becomes readable once the
+/// process has exited.
+struct PidFd(OwnedFd);
+
+impl PidFd {
+    #[cfg(target_os = "linux")]
+    fn open(pid: u32) -> io::Result<Self> {
+        // SAFETY: `pidfd_open` takes a PID and flags, and returns a new file
+        // descriptor that nobody else owns.
+        let fd = unsafe { ffi::syscall(ffi::SYS_PIDFD_OPEN, pid as i64, 0i64) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `fd` was just returned by `pidfd_open`, so it's open, and
+        // nothing else owns it.
+        Ok(Self(unsafe { OwnedFd::from_raw_fd(fd as i32) }))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn open(_pid: u32) -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+impl Source for PidFd {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.0.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.0.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.0.as_raw_fd()).deregister(registry)
+    }
+}
+
+/// Starts a thread that waits for the process `pid` to exit, and then wakes
+/// whoever is waiting on the slot it returns. It leaves the process for us to
+/// reap.
+fn wait_in_thread(pid: u32) -> io::Result<Arc<Mutex<ExitSlot>>> {
+    let slot = Arc::new(Mutex::new(ExitSlot::default()));
+    let shared = slot.clone();
+    // Like in `blocking::spawn_blocking`, waking the task isn't enough to get
+    // it polled if the executor drives the reactor inline.
+    let inline = reactor::inline_reactor();
+    thread::Builder::new()
+        .name(format!("wait-{pid}"))
+        .spawn(move || {
+            // Big enough for a `siginfo_t`, which we don't look at.
+            let mut info = [0u8; 128];
+            loop {
+                // SAFETY: `info` is big enough for the `siginfo_t` that
+                // `waitid` writes to.
+                let res = unsafe {
+                    ffi::waitid(
+                        ffi::P_PID,
+                        pid,
+                        info.as_mut_ptr(),
+                        ffi::WEXITED | ffi::WNOWAIT,
+                    )
+                };
+                if res == 0
+                    || io::Error::last_os_error().kind()
+                        != io::ErrorKind::Interrupted
+                {
+                    break;
+                }
+            }
+            let mut slot = shared.lock().unwrap();
+            slot.exited = true;
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+                if let Some(reactor) = inline {
+                    reactor.notify();
+                }
+            }
+        })?;
+    Ok(slot)
+}
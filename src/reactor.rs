@@ -1,61 +1,224 @@
 use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex, OnceLock, atomic::AtomicUsize},
+    cell::Cell,
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    io,
+    sync::{
+        Arc, Mutex, MutexGuard, RwLock, TryLockError,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
-use mio::{Events, Interest, Poll, Token, net::TcpStream};
+use mio::{Events, Interest, Poll, Token, event::Event, event::Source};
 
-use crate::executor::Waker;
+use crate::{
+    executor::Waker,
+    future_with_waker::PollState,
+    logging,
+    trace::{self, EventKind},
+};
+
+// The token we register the reactor's own `mio::Waker` with. It's never handed
+// out by `next_id`, so it can't clash with the ID of a source.
+const WAKE_TOKEN: Token = Token(usize::MAX);
 
-type Wakers = Arc<Mutex<HashMap<usize, Waker>>>;
+// Holds the shards of the reactor that's currently running, if any. Unlike a
+// `OnceLock`, this allows us to shut the reactor down and start a new one
+// later on, while still making sure that there's only a single instance
+// running at a time. An empty `Vec` means that the reactor isn't running.
+//
+// Leaf futures keep their own `Arc` to the shard they registered with, so
+// they can tell when it has been shut down, even if a new one has been started
+// since.
+static REACTOR: RwLock<Vec<Arc<Reactor>>> = RwLock::new(Vec::new());
 
-// OnceLock allows us to define a static variable that we can write to once
-// so that we can initialise it when we start our reactor. By doing so, we also
-// make sure that there can only be a single instance of this specific reactor
-// running in our program.
-static REACTOR: OnceLock<Reactor> = OnceLock::new();
+// Hands out shards to threads, round robin.
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // The shard this thread registers its sources with. Every task that an
+    // executor runs registers with the same shard, so the executors don't
+    // compete for the same locks and the same event loop thread.
+    static SHARD: Cell<Option<usize>> = const { Cell::new(None) };
+}
 
-/// Returns a reference to the reactor instance.
-pub fn reactor() -> &'static Reactor {
-    REACTOR.get().expect("Called outside a runtime context")
+/// Returns a reference to the reactor instance. If the reactor is sharded,
+/// this is the shard that belongs to the current thread.
+pub fn reactor() -> Arc<Reactor> {
+    try_reactor().expect("Called outside a runtime context")
+}
+
+/// Returns a reference to the reactor instance, or `None` if it isn't
+/// running.
+pub fn try_reactor() -> Option<Arc<Reactor>> {
+    let shards = REACTOR.read().unwrap();
+    if shards.is_empty() {
+        return None;
+    }
+    let shard = SHARD.with(|shard| {
+        shard.get().unwrap_or_else(|| {
+            let next = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+            shard.set(Some(next));
+            next
+        })
+    });
+    // A new reactor may have fewer shards than the one the thread was
+    // assigned a shard of.
+    Some(shards[shard % shards.len()].clone())
+}
+
+/// Returns the reactor if it was started with `start_inline`. The executor
+/// uses this to find out whether it has to drive the reactor itself.
+pub(crate) fn inline_reactor() -> Option<Arc<Reactor>> {
+    try_reactor().filter(|r| r.is_inline())
+}
+
+/// How the reactor should be set up. `runtime_two::Builder` has a method
+/// for each of these.
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    /// The number of shards. See `start_sharded`.
+    pub shards: usize,
+    /// The number of events we receive from `Poll` at once. If more events
+    /// than this are pending, we pick up the rest in further batches before
+    /// we block again.
+    pub event_capacity: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            shards: 1,
+            event_capacity: 1024,
+        }
+    }
 }
 
 /// Initialises and starts the reactor.
 pub fn start() {
-    use std::thread::spawn;
+    start_with(Config::default());
+}
+
+/// Initialises and starts a reactor with `shards` shards, each with a `Poll`
+/// instance, a map of sources and an event loop thread of its own.
+///
+/// Every thread that registers a source is assigned one of the shards, round
+/// robin, and keeps using it from then on. With many executors, this means
+/// that wakeups no longer all go through a single event loop thread and a
+/// single lock.
+///
+/// Panics if `shards` is zero.
+pub fn start_sharded(shards: usize) {
+    start_with(Config {
+        shards,
+        ..Config::default()
+    });
+}
 
-    let wakers = Arc::new(Mutex::new(HashMap::new()));
+/// Initialises and starts the reactor as described by `config`.
+pub fn start_with(config: Config) {
+    use std::thread::Builder;
 
-    let poll = Poll::new().unwrap();
-    let registry = poll.registry().try_clone().unwrap();
-    let next_id = AtomicUsize::new(1);
-    let reactor = Reactor {
-        wakers: wakers.clone(),
-        registry,
-        next_id,
-    };
-
-    REACTOR
-        .set(reactor)
-        .ok()
-        .expect("Reactor is already running");
-
-    // We spawn a new OS thread and start our event loop function on that one.
-    // This also means that pass on our `Poll` instance to the event loop
-    // thread for good.
+    let Config {
+        shards,
+        event_capacity,
+    } = config;
+    assert!(shards > 0, "The reactor needs at least one shard");
+    assert!(event_capacity > 0, "The event capacity must be non-zero");
+    let polls: Vec<_> = (0..shards).map(|_| Poll::new().unwrap()).collect();
+    let reactors: Vec<_> = polls
+        .iter()
+        .map(|poll| Arc::new(Reactor::new(poll, false)))
+        .collect();
+    install(reactors.clone());
+
+    // We spawn a new OS thread for each shard and start our event loop
+    // function on that one. This also means that pass on our `Poll` instances
+    // to the event loop threads for good.
     //
-    // The best practice would be to store the `JoinHandle` returned from
-    // `spawn` so that can join the thread later on, but our thread has no way
-    // to shut down the event loop anyway, so joining it later on makes little
-    // sense, and we simply discard the handle.
-    spawn(move || {
-        event_loop(poll, wakers);
-    });
+    // We store the `JoinHandle` returned from `spawn` so that `shutdown` can
+    // join the thread once it has broken out of the event loop. We name the
+    // thread so that it's easy to find in a trace.
+    logging::info!("Starting the reactor with {shards} shard(s)");
+    for (i, (poll, reactor)) in polls.into_iter().zip(reactors).enumerate() {
+        let name = match shards {
+            1 => "reactor".to_string(),
+            _ => format!("reactor-{i}"),
+        };
+        let event_loop_reactor = reactor.clone();
+        let handle = Builder::new()
+            .name(name)
+            .spawn(move || {
+                let driver = Driver::new(poll, event_capacity);
+                event_loop(driver, event_loop_reactor);
+            })
+            .unwrap();
+        *reactor.thread.lock().unwrap() = Some(handle);
+    }
+}
+
+/// Initialises the reactor without an event loop thread. Instead, the
+/// `Executor` calls `Reactor::turn` itself when it runs out of ready tasks,
+/// where it would otherwise park its thread.
+///
+/// This saves the two context switches per event that it takes for the
+/// reactor thread to wake up and then unpark the executor thread, but it only
+/// works with a single executor: there is only one `Poll` instance, and an
+/// executor that's blocked waiting for events on it won't notice a `Waker`
+/// being called from a different thread.
+pub fn start_inline() {
+    start_inline_with(Config::default());
+}
+
+/// Like `start_inline`, but as described by `config`.
+///
+/// Panics if `config` asks for more than one shard.
+pub fn start_inline_with(config: Config) {
+    assert!(config.shards == 1, "An inline reactor can't be sharded");
+    assert!(
+        config.event_capacity > 0,
+        "The event capacity must be non-zero"
+    );
+    logging::info!("Starting the reactor inline");
+    let poll = Poll::new().unwrap();
+    install(vec![Arc::new(Reactor::new(&poll, true))]);
+    let reactor = reactor();
+    let driver = Driver::new(poll, config.event_capacity);
+    *reactor.inline.as_ref().unwrap().lock().unwrap() = Some(driver);
+}
+
+/// Makes `shards` the running reactor.
+fn install(shards: Vec<Arc<Reactor>>) {
+    let mut current = REACTOR.write().unwrap();
+    assert!(current.is_empty(), "Reactor is already running");
+    *current = shards;
+}
+
+/// Returns how often the locks on the sources of all the shards have been
+/// taken, and how often we had to wait for them.
+pub fn lock_stats() -> LockStats {
+    REACTOR.read().unwrap().iter().map(|r| r.lock_stats()).fold(
+        LockStats::default(),
+        |a, b| LockStats {
+            acquisitions: a.acquisitions + b.acquisitions,
+            contended: a.contended + b.contended,
+        },
+    )
+}
+
+/// See `lock_stats`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LockStats {
+    pub acquisitions: u64,
+    pub contended: u64,
 }
 
 pub struct Reactor {
-    // A HashMap of Waker objects, each identified by an integer ID.
-    wakers: Wakers,
+    // The readiness and the Wakers of every registered source, each
+    // identified by an integer ID.
+    sources: Mutex<HashMap<usize, ScheduledIo>>,
 
     // Holds a `Registry` instance so that we can interact with the event queue
     // in `mio`.
@@ -64,42 +227,152 @@ pub struct Reactor {
     // Stores the next available ID so that we can track which event occurred
     // and which `Waker` should be woken up.
     next_id: AtomicUsize,
+
+    // When the reactor runs inline, the `Poll` instance lives here instead of
+    // on the event loop thread, so that the executor can drive it.
+    inline: Option<Mutex<Option<Driver>>>,
+
+    // Wakes up whoever is blocked waiting for events, so that they notice
+    // that the reactor is shutting down.
+    mio_waker: mio::Waker,
+
+    // The timers of the `time::Sleep` futures that are waiting. Whoever drives
+    // the `Poll` instance never blocks for longer than it takes until the
+    // first of them expires.
+    timers: Mutex<Timers>,
+
+    // Set by `shutdown`.
+    shutdown: AtomicBool,
+
+    // Counts how often `sources` was locked, and how often someone else was
+    // holding the lock already. See `lock_stats`.
+    acquisitions: AtomicU64,
+    contended: AtomicU64,
+
+    // The event loop thread, if the reactor has one.
+    thread: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl Reactor {
+    fn new(poll: &Poll, inline: bool) -> Self {
+        let registry = poll.registry().try_clone().unwrap();
+        let mio_waker = mio::Waker::new(&registry, WAKE_TOKEN).unwrap();
+        Self {
+            sources: Mutex::new(HashMap::new()),
+            registry,
+            next_id: AtomicUsize::new(1),
+            inline: inline.then(|| Mutex::new(None)),
+            mio_waker,
+            timers: Mutex::new(Timers::default()),
+            shutdown: AtomicBool::new(false),
+            acquisitions: AtomicU64::new(0),
+            contended: AtomicU64::new(0),
+            thread: Mutex::new(None),
+        }
+    }
+
+    /// Locks the map of sources, keeping count of how often we have to wait.
+    fn sources(&self) -> MutexGuard<'_, HashMap<usize, ScheduledIo>> {
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        match self.sources.try_lock() {
+            Ok(sources) => sources,
+            Err(TryLockError::WouldBlock) => {
+                self.contended.fetch_add(1, Ordering::Relaxed);
+                self.sources.lock().unwrap()
+            }
+            Err(TryLockError::Poisoned(e)) => panic!("{e}"),
+        }
+    }
+
+    fn lock_stats(&self) -> LockStats {
+        LockStats {
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            contended: self.contended.load(Ordering::Relaxed),
+        }
+    }
+
     /// Register with the `Registry`. We pass in an ID property so that we can
     /// identify which event has occurred when we receive a notification later
     /// on.
-    pub fn register(
+    ///
+    /// Any `mio` source can be registered: TCP and UDP sockets, listeners,
+    /// Unix sockets, pipes and so on. Most leaf futures won't call this
+    /// directly, but go through `io::PollEvented`, which takes care of the
+    /// ID, the Wakers and deregistering the source again.
+    pub fn register<S: Source + ?Sized>(
         &self,
-        stream: &mut TcpStream,
+        source: &mut S,
         interest: Interest,
         id: usize,
-    ) {
-        self.registry
-            .register(stream, Token(id), interest)
-            .expect("Failed to register stream with reactor");
+    ) -> io::Result<()> {
+        self.sources().insert(id, ScheduledIo::new(interest));
+        if let Err(e) = self.registry.register(source, Token(id), interest) {
+            self.sources().remove(&id);
+            return Err(e);
+        }
+        logging::debug!("Registered source {id} with {interest:?}");
+        trace::record(EventKind::Register, id);
+        Ok(())
     }
 
-    /// Adds a waker to our HashMap using the ID property as the key. If there
-    /// is Waker already there, we replace it and drop the old one.
+    /// Checks whether the source with the given ID is ready in `direction`.
+    /// If it isn't, `waker` is stored so that it's woken once it is, and we
+    /// return `NotReady`. Sources that were never registered (or have been
+    /// deregistered) are never ready.
     ///
     /// An important point to remember is that **we should always store the
     /// most recent Waker** so that this function can be called multiple times,
-    /// even though there is already a Waker associated with the `TcpStream`.
-    pub fn set_waker(&self, waker: &Waker, id: usize) {
-        let _ = self
-            .wakers
-            .lock()
-            .map(|mut w| w.insert(id, waker.clone()).is_none())
-            .unwrap();
+    /// even though there is already a Waker stored for the source. There is
+    /// one Waker per direction, so that a task reading from a source and
+    /// another one writing to it don't replace each other's Wakers.
+    pub fn poll_ready(
+        &self,
+        id: usize,
+        direction: Direction,
+        waker: &Waker,
+    ) -> PollState<ReadyEvent> {
+        let mut sources = self.sources();
+        let Some(io) = sources.get_mut(&id) else {
+            return PollState::NotReady;
+        };
+        let readiness = io.readiness.intersection(direction.mask());
+        if !readiness.is_empty() {
+            return PollState::Ready(ReadyEvent {
+                tick: io.tick,
+                direction,
+                readiness,
+            });
+        }
+        *io.waker(direction) = Some(waker.clone());
+        PollState::NotReady
+    }
+
+    /// Clears the readiness `event` reported, once an operation in its
+    /// direction has failed with `WouldBlock`. If an event has arrived since
+    /// `poll_ready` returned it, the readiness is left alone: we can't tell
+    /// whether the event came before or after the operation, so the caller
+    /// gets to try again.
+    pub fn clear_readiness(&self, id: usize, event: ReadyEvent) {
+        let mut sources = self.sources();
+        if let Some(io) = sources.get_mut(&id)
+            && io.tick == event.tick
+        {
+            io.readiness = io.readiness.difference(event.direction.clears());
+        }
     }
 
-    /// Removes the Waker from the HashMap and deregisters the `TcpStream` from
-    /// our `Registry`.
-    pub fn deregister(&self, stream: &mut TcpStream, id: usize) {
-        self.wakers.lock().map(|mut w| w.remove(&id)).unwrap();
-        self.registry.deregister(stream).unwrap();
+    /// Removes the source from the HashMap, dropping its Wakers, and
+    /// deregisters it from our `Registry`.
+    pub fn deregister<S: Source + ?Sized>(
+        &self,
+        source: &mut S,
+        id: usize,
+    ) -> io::Result<()> {
+        self.sources().remove(&id);
+        let result = self.registry.deregister(source);
+        logging::debug!("Deregistered source {id}");
+        trace::record(EventKind::Deregister, id);
+        result
     }
 
     /// Gets the current `next_id` value and incremements the counter atomically.
@@ -107,36 +380,474 @@ impl Reactor {
     /// care about not handing out the same value twice, so `Ordering::Relaxed`
     /// will suffice here.
     pub fn next_id(&self) -> usize {
-        self.next_id
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Arranges for `waker` to be woken once `deadline` has passed. Just like
+    /// `poll_ready`, this can be called again with the same ID to replace the
+    /// Waker (or move the deadline).
+    ///
+    /// If the new deadline is earlier than any other, the event loop thread
+    /// may be blocked waiting for longer than it should, so we wake it up to
+    /// have it work out a new timeout. When the reactor runs inline, there is
+    /// no need for this: timers are only set by the tasks of the executor
+    /// that drives it, so it will see the new deadline the next time it calls
+    /// `turn`.
+    pub fn set_timer(&self, id: usize, deadline: Instant, waker: &Waker) {
+        let earliest = self.timers.lock().unwrap().insert(id, deadline, waker);
+        if earliest && !self.is_inline() {
+            self.mio_waker.wake().unwrap();
+        }
+    }
+
+    /// Removes the timer with the given ID, if it hasn't fired yet.
+    pub fn cancel_timer(&self, id: usize) {
+        self.timers.lock().unwrap().remove(id);
+    }
+
+    /// Interrupts whoever is waiting for events. An executor that drives the
+    /// reactor inline waits in `turn` instead of parking, so this is how a
+    /// thread that wakes one of its tasks makes sure it notices.
+    pub(crate) fn notify(&self) {
+        self.mio_waker.wake().unwrap();
+    }
+
+    /// Returns `true` if the reactor was started with `start_inline`.
+    pub fn is_inline(&self) -> bool {
+        self.inline.is_some()
+    }
+
+    /// Waits for events for at most `timeout` (forever if `None`) and wakes
+    /// the tasks they belong to. This is what the event loop thread does over
+    /// and over again, and what the executor does instead of parking when the
+    /// reactor runs inline.
+    ///
+    /// Panics if the reactor wasn't started with `start_inline`.
+    pub fn turn(&self, timeout: Option<Duration>) {
+        let driver = self.inline.as_ref().expect("Reactor is not inline");
+        if let Some(driver) = driver.lock().unwrap().as_mut() {
+            driver.turn(self, timeout);
+        }
+    }
+
+    /// Returns `true` once `shutdown` has been called.
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::Acquire)
+    }
+
+    /// Returns an error if the reactor has been shut down. Leaf futures call
+    /// this when they're polled, so that a future whose reactor has gone away
+    /// fails instead of waiting for an event that will never come.
+    pub fn check_shutdown(&self) -> io::Result<()> {
+        if self.is_shutdown() {
+            Err(io::Error::other("the reactor has been shut down"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Shuts the reactor down, with all of its shards.
+    ///
+    /// We break the event loop by setting the shutdown flag and waking up the
+    /// `Poll` instance through our `mio::Waker`, and then join the event loop
+    /// thread. After that, we wake every task that's still waiting for an
+    /// event, and since `check_shutdown` now returns an error, their leaf
+    /// futures fail the next time they're polled.
+    ///
+    /// Once this returns, `start` (or `start_inline`) can be called again to
+    /// start a new reactor.
+    pub fn shutdown(&self) {
+        // Make room for a new reactor, unless someone has already started
+        // one, in which case this shard belongs to an old reactor that has
+        // already been shut down.
+        let shards = {
+            let mut current = REACTOR.write().unwrap();
+            if current.iter().any(|r| std::ptr::eq(&**r, self)) {
+                std::mem::take(&mut *current)
+            } else {
+                vec![]
+            }
+        };
+        if shards.is_empty() {
+            self.shutdown_shard();
+        }
+        for shard in shards {
+            shard.shutdown_shard();
+        }
+    }
+
+    fn shutdown_shard(&self) {
+        if self.shutdown.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        logging::info!("Shutting down the reactor");
+        self.mio_waker.wake().unwrap();
+        if let Some(handle) = self.thread.lock().unwrap().take() {
+            handle.join().expect("The event loop thread panicked");
+        }
+        if let Some(driver) = &self.inline {
+            driver.lock().unwrap().take();
+        }
+
+        // Take the Wakers out of the map before we call them, so that we
+        // don't hold the lock while the tasks are being woken.
+        let sources = std::mem::take(&mut *self.sources());
+        let timers = std::mem::take(&mut *self.timers.lock().unwrap());
+        let wakers = sources.into_values().flat_map(ScheduledIo::into_wakers);
+        for waker in wakers.chain(timers.into_wakers()) {
+            waker.wake();
+        }
     }
 }
 
-// Loop forever. This makes the example short and simple, but it has the
-// downside that we have no way of shutting our event loop down once it's
-// started.  Fixing is not that difficult, but since it won't be necessary
-// for our example, we won't do it here.
-fn event_loop(mut poll: Poll, wakers: Wakers) {
-    let mut events = Events::with_capacity(100);
-    loop {
-        // Call `poll` with a timeout of `None`, which means that it will block
-        // until an event occurs.
-        poll.poll(&mut events, None).unwrap();
-        // If we receive an event, it means that something we registered
-        // interest in has happened. We get the `id` we passed in when we
-        // first registered an interest in events on this `TcpStream`.
-        for e in events.iter() {
-            let Token(id) = e.token();
-            let wakers = wakers.lock().unwrap();
-            // We try to get the associated Waker and call `wake` on it.
-            // We guard ourselves from the fact that the Waker may have been
-            // removed from our collection already, in which case we do nothing.
-            //
-            // It's worth noting that we can filter events if we want to here.
-            // For our example, we don't need to filter events.
-            if let Some(waker) = wakers.get(&id) {
-                waker.wake();
+// -----------------------------------------------------------------------------
+
+// The most batches of events we pick up in one turn. Under a constant stream
+// of events, we'd otherwise never get round to expiring timers or noticing
+// that the reactor has been shut down.
+const MAX_BATCHES: usize = 16;
+
+/// Owns the `Poll` instance and the buffer we receive events in.
+struct Driver {
+    poll: Poll,
+    events: Events,
+    // The Wakers of a batch of events. We keep the `Vec` around so that we
+    // don't allocate a new one for every batch.
+    wakers: Vec<Waker>,
+}
+
+impl Driver {
+    fn new(poll: Poll, event_capacity: usize) -> Self {
+        Self {
+            poll,
+            events: Events::with_capacity(event_capacity),
+            wakers: vec![],
+        }
+    }
+
+    /// Blocks until at least one event has occurred, `timeout` has passed or
+    /// the next timer is due, and wakes the tasks the events and the expired
+    /// timers belong to.
+    fn turn(&mut self, reactor: &Reactor, timeout: Option<Duration>) {
+        // Don't sleep past the first deadline. `mio` rounds the timeout up to
+        // the resolution of the system call it uses, so we won't wake up
+        // before the deadline and have to go round again.
+        let deadline = reactor.timers.lock().unwrap().next_deadline();
+        let mut timeout = match deadline {
+            Some(deadline) => {
+                let until = deadline.saturating_duration_since(Instant::now());
+                Some(timeout.map_or(until, |t| t.min(until)))
+            }
+            None => timeout,
+        };
+
+        for _ in 0..MAX_BATCHES {
+            self.poll.poll(&mut self.events, timeout).unwrap();
+            self.dispatch(reactor);
+            // If the buffer was full, there may be more events waiting. We
+            // pick them up straight away (without blocking) rather than
+            // leaving them for the next turn.
+            if self.events.iter().count() < self.events.capacity() {
+                break;
+            }
+            timeout = Some(Duration::ZERO);
+        }
+
+        // We take the expired timers out of the heap before we wake their
+        // tasks, so that we don't hold the lock while we do.
+        let expired = reactor.timers.lock().unwrap().expire(Instant::now());
+        for (id, waker) in expired {
+            logging::trace!("Timer {id} expired");
+            waker.wake();
+        }
+    }
+
+    /// Records the readiness of a batch of events and wakes the tasks that
+    /// were waiting for it.
+    ///
+    /// We lock the map of sources once for the whole batch, and only collect
+    /// the Wakers while we hold the lock. Calling `wake` unparks the
+    /// executor's thread, which is then likely to want the lock itself to
+    /// register a new Waker, so we release it before we wake anyone.
+    fn dispatch(&mut self, reactor: &Reactor) {
+        {
+            let mut sources = reactor.sources();
+            // If we receive an event, it means that something we registered
+            // interest in has happened. We get the `id` we passed in when we
+            // first registered an interest in events on this source.
+            for e in self.events.iter() {
+                // The reactor's own `mio::Waker` only wakes us up so that the
+                // event loop can check whether it has been shut down.
+                if e.token() == WAKE_TOKEN {
+                    continue;
+                }
+                let Token(id) = e.token();
+                logging::trace!("Event for source {id}: {e:?}");
+                trace::record(
+                    EventKind::Readiness {
+                        readable: e.is_readable(),
+                        writable: e.is_writable(),
+                    },
+                    id,
+                );
+                // We try to get the associated source, record the new
+                // readiness and collect the Wakers waiting for it. We guard
+                // ourselves from the fact that the source may have been
+                // removed from our collection already, in which case we do
+                // nothing.
+                if let Some(io) = sources.get_mut(&id) {
+                    let readiness = Readiness::from_event(e);
+                    io.set_readiness(readiness, &mut self.wakers);
+                }
+            }
+        }
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// The direction a task is waiting for a source to become ready in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Read,
+    Write,
+}
+
+impl Direction {
+    /// The readiness that a task waiting in this direction wants to know
+    /// about. Errors, and the side it's waiting on being closed, are passed
+    /// through: the next operation will fail or return 0 straight away, and
+    /// the task should find out.
+    fn mask(self) -> Readiness {
+        match self {
+            Direction::Read => Readiness::READABLE
+                .union(Readiness::READ_CLOSED)
+                .union(Readiness::ERROR),
+            Direction::Write => Readiness::WRITABLE
+                .union(Readiness::WRITE_CLOSED)
+                .union(Readiness::ERROR),
+        }
+    }
+
+    /// The readiness that a `WouldBlock` in this direction clears. Closed
+    /// and error states stick around for good.
+    fn clears(self) -> Readiness {
+        match self {
+            Direction::Read => Readiness::READABLE,
+            Direction::Write => Readiness::WRITABLE,
+        }
+    }
+}
+
+/// A set of readiness states, as reported by `mio`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Readiness(u8);
+
+impl Readiness {
+    pub const EMPTY: Readiness = Readiness(0);
+    pub const READABLE: Readiness = Readiness(0b00001);
+    pub const WRITABLE: Readiness = Readiness(0b00010);
+    pub const READ_CLOSED: Readiness = Readiness(0b00100);
+    pub const WRITE_CLOSED: Readiness = Readiness(0b01000);
+    pub const ERROR: Readiness = Readiness(0b10000);
+
+    fn from_event(e: &Event) -> Self {
+        [
+            (e.is_readable(), Readiness::READABLE),
+            (e.is_writable(), Readiness::WRITABLE),
+            (e.is_read_closed(), Readiness::READ_CLOSED),
+            (e.is_write_closed(), Readiness::WRITE_CLOSED),
+            (e.is_error(), Readiness::ERROR),
+        ]
+        .into_iter()
+        .filter(|(set, _)| *set)
+        .fold(Readiness::EMPTY, |acc, (_, r)| acc.union(r))
+    }
+
+    pub const fn union(self, other: Readiness) -> Readiness {
+        Readiness(self.0 | other.0)
+    }
+
+    pub const fn intersection(self, other: Readiness) -> Readiness {
+        Readiness(self.0 & other.0)
+    }
+
+    pub const fn difference(self, other: Readiness) -> Readiness {
+        Readiness(self.0 & !other.0)
+    }
+
+    pub const fn contains(self, other: Readiness) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn is_readable(self) -> bool {
+        self.contains(Readiness::READABLE)
+    }
+
+    pub const fn is_writable(self) -> bool {
+        self.contains(Readiness::WRITABLE)
+    }
+
+    pub const fn is_read_closed(self) -> bool {
+        self.contains(Readiness::READ_CLOSED)
+    }
+
+    pub const fn is_write_closed(self) -> bool {
+        self.contains(Readiness::WRITE_CLOSED)
+    }
+
+    pub const fn is_error(self) -> bool {
+        self.contains(Readiness::ERROR)
+    }
+}
+
+/// The readiness `Reactor::poll_ready` found. Hand it back to
+/// `Reactor::clear_readiness` if the operation would block after all.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadyEvent {
+    // The number of events the source had seen when we looked.
+    tick: u64,
+    direction: Direction,
+    pub readiness: Readiness,
+}
+
+/// What the reactor knows about a registered source.
+///
+/// Since `mio` only tells us when the readiness of a source *changes*, we
+/// have to remember it until the source is actually read from or written to
+/// and the operation would block. We start out assuming that the source is
+/// ready for whatever we registered interest in, so that the first operation
+/// is tried straight away instead of waiting for an event first.
+struct ScheduledIo {
+    readiness: Readiness,
+    // Counts the events we've received, so that `clear_readiness` can tell
+    // whether there has been a new one.
+    tick: u64,
+    reader: Option<Waker>,
+    writer: Option<Waker>,
+}
+
+impl ScheduledIo {
+    fn new(interest: Interest) -> Self {
+        let mut readiness = Readiness::EMPTY;
+        if interest.is_readable() {
+            readiness = readiness.union(Readiness::READABLE);
+        }
+        if interest.is_writable() {
+            readiness = readiness.union(Readiness::WRITABLE);
+        }
+        Self {
+            readiness,
+            tick: 0,
+            reader: None,
+            writer: None,
+        }
+    }
+
+    fn waker(&mut self, direction: Direction) -> &mut Option<Waker> {
+        match direction {
+            Direction::Read => &mut self.reader,
+            Direction::Write => &mut self.writer,
+        }
+    }
+
+    /// Records the readiness of a new event and adds the Wakers of the
+    /// directions it concerns to `wakers`.
+    fn set_readiness(&mut self, readiness: Readiness, wakers: &mut Vec<Waker>) {
+        self.readiness = self.readiness.union(readiness);
+        self.tick += 1;
+        for direction in [Direction::Read, Direction::Write] {
+            if !readiness.intersection(direction.mask()).is_empty() {
+                wakers.extend(self.waker(direction).take());
+            }
+        }
+    }
+
+    fn into_wakers(self) -> impl Iterator<Item = Waker> {
+        self.reader.into_iter().chain(self.writer)
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// The timers that are waiting to expire.
+///
+/// The deadlines are kept in a binary heap so that we can find the first one
+/// quickly. Changing or removing a timer would mean searching the heap, so
+/// instead we only update `entries` and leave the old deadline in the heap.
+/// Entries in the heap that don't match `entries` any more are skipped when
+/// they come up.
+#[derive(Default)]
+struct Timers {
+    heap: BinaryHeap<Reverse<(Instant, usize)>>,
+    entries: HashMap<usize, (Instant, Waker)>,
+}
+
+impl Timers {
+    /// Adds or updates a timer. Returns `true` if it's now the first one to
+    /// expire.
+    fn insert(&mut self, id: usize, deadline: Instant, waker: &Waker) -> bool {
+        let earliest = self.next_deadline().is_none_or(|d| deadline < d);
+        let previous = self.entries.insert(id, (deadline, waker.clone()));
+        if previous.is_none_or(|(d, _)| d != deadline) {
+            self.heap.push(Reverse((deadline, id)));
+        }
+        earliest
+    }
+
+    fn remove(&mut self, id: usize) {
+        self.entries.remove(&id);
+    }
+
+    /// Returns the first deadline, dropping any stale entries in the heap on
+    /// the way.
+    fn next_deadline(&mut self) -> Option<Instant> {
+        while let Some(Reverse((deadline, id))) = self.heap.peek() {
+            if self.is_current(*id, *deadline) {
+                return Some(*deadline);
             }
+            self.heap.pop();
         }
+        None
+    }
+
+    /// Removes the timers whose deadline is at or before `now` and returns
+    /// their IDs and Wakers.
+    fn expire(&mut self, now: Instant) -> Vec<(usize, Waker)> {
+        let mut expired = vec![];
+        while let Some(deadline) = self.next_deadline() {
+            if deadline > now {
+                break;
+            }
+            let Reverse((_, id)) = self.heap.pop().unwrap();
+            let (_, waker) = self.entries.remove(&id).unwrap();
+            expired.push((id, waker));
+        }
+        expired
+    }
+
+    fn is_current(&self, id: usize, deadline: Instant) -> bool {
+        self.entries.get(&id).is_some_and(|(d, _)| *d == deadline)
+    }
+
+    fn into_wakers(self) -> impl Iterator<Item = Waker> {
+        self.entries.into_values().map(|(_, waker)| waker)
+    }
+}
+
+// Loop until the reactor is shut down. `shutdown` wakes us up through the
+// reactor's `mio::Waker` so that we notice it straight away.
+fn event_loop(mut driver: Driver, reactor: Arc<Reactor>) {
+    while !reactor.is_shutdown() {
+        // Call `poll` with a timeout of `None`, which means that it will block
+        // until an event occurs or the next timer expires.
+        driver.turn(&reactor, None);
     }
 }
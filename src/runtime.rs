@@ -1,4 +1,7 @@
-use crate::future::{Future, PollState};
+use crate::{
+    future::{Future, PollState},
+    logging,
+};
 use std::sync::OnceLock;
 
 use mio::{Events, Poll, Registry};
@@ -31,7 +34,7 @@ impl Runtime {
         loop {
             match future.poll() {
                 PollState::NotReady => {
-                    println!("Schedule other tasks\n");
+                    logging::debug!("Schedule other tasks");
                     let mut events = Events::with_capacity(100);
                     // Wait for events on the poller.
                     // This yields to the OS scheduler.
@@ -1,6 +1,114 @@
-use crate::{executor::Executor, reactor};
+use std::time::Duration;
+
+use crate::{
+    blocking,
+    executor::Executor,
+    logging::{self, Filter},
+    reactor,
+};
 
 pub fn init() -> Executor {
-    reactor::start();
-    Executor::new()
+    Builder::new().build()
+}
+
+/// Configures the runtime before it's started.
+#[derive(Default)]
+pub struct Builder {
+    budget: Option<usize>,
+    inline_reactor: bool,
+    reactor_shards: Option<usize>,
+    event_capacity: Option<usize>,
+    stall_timeout: Option<Duration>,
+    log_filter: Option<Filter>,
+    blocking_threads: Option<usize>,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gives every task a poll budget of `budget` units of work per tick.
+    /// See `Executor::budget` and `executor::consume_budget`.
+    ///
+    /// Panics if `budget` is zero.
+    pub fn budget(mut self, budget: usize) -> Self {
+        assert!(budget > 0, "`budget` must be non-zero");
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Runs the reactor on the executor's thread instead of on a dedicated
+    /// event loop thread. See `reactor::start_inline`.
+    pub fn inline_reactor(mut self, inline: bool) -> Self {
+        self.inline_reactor = inline;
+        self
+    }
+
+    /// Splits the reactor into `shards` shards, each with an event loop
+    /// thread of its own. See `reactor::start_sharded`.
+    pub fn reactor_shards(mut self, shards: usize) -> Self {
+        self.reactor_shards = Some(shards);
+        self
+    }
+
+    /// Sets how many events the reactor receives from the OS at once. See
+    /// `reactor::Config::event_capacity`.
+    pub fn event_capacity(mut self, capacity: usize) -> Self {
+        self.event_capacity = Some(capacity);
+        self
+    }
+
+    /// Turns on stall detection in the executor. See
+    /// `Executor::detect_stalls`.
+    pub fn stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = Some(timeout);
+        self
+    }
+
+    /// Limits how many threads the blocking pool runs at once. See
+    /// `blocking::set_max_threads`.
+    pub fn blocking_threads(mut self, threads: usize) -> Self {
+        self.blocking_threads = Some(threads);
+        self
+    }
+
+    /// Turns on logging for the runtime, using the same syntax as the
+    /// `RUNTIME_LOG` environment variable (see `logging::Filter`), for
+    /// example `"info"` or `"warn,reactor=trace"`. This takes precedence
+    /// over the environment variable.
+    pub fn log_filter(mut self, spec: &str) -> Self {
+        self.log_filter = Some(Filter::parse(spec));
+        self
+    }
+
+    /// Starts the reactor and returns the executor.
+    pub fn build(self) -> Executor {
+        if let Some(filter) = self.log_filter {
+            logging::set_filter(filter);
+        }
+        if let Some(threads) = self.blocking_threads {
+            blocking::set_max_threads(threads);
+        }
+        let defaults = reactor::Config::default();
+        let config = reactor::Config {
+            shards: self.reactor_shards.unwrap_or(defaults.shards),
+            event_capacity: self
+                .event_capacity
+                .unwrap_or(defaults.event_capacity),
+        };
+        if self.inline_reactor {
+            reactor::start_inline_with(config);
+        } else {
+            reactor::start_with(config);
+        }
+        let mut executor = Executor::new();
+        if let Some(budget) = self.budget {
+            executor = executor.budget(budget);
+        }
+        if let Some(timeout) = self.stall_timeout {
+            executor = executor.detect_stalls(timeout);
+        }
+        executor
+    }
 }
@@ -0,0 +1,386 @@
+use std::{
+    cell::RefCell,
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    ops::Range,
+    panic::{self, AssertUnwindSafe},
+    time::Duration,
+};
+
+use crate::{
+    executor::{self, Waker},
+    future_with_waker::{Future, PollState},
+};
+
+thread_local! {
+    // The simulated world that the tasks on this thread live in while a
+    // `SimExecutor` is running. It holds the virtual clock, the timers that
+    // our fake reactor has to fire and the random number generator that makes
+    // all the scheduling decisions.
+    static WORLD: RefCell<Option<World>> = const { RefCell::new(None) };
+}
+
+/// A deterministic executor for testing our coroutines.
+///
+/// The real `Executor` polls tasks in the order their `Waker`s are called,
+/// and that order depends on the timing of the network and the delayserver.
+/// That makes concurrency bugs hard to reproduce. The simulation executor
+/// replaces everything that isn't deterministic:
+///
+/// * The next task to poll is picked at random from the ready queue, using a
+///   random number generator seeded with `seed`.
+/// * Time is a virtual clock that only moves forward when every task is
+///   waiting for a timer, and then jumps straight to the next deadline.
+/// * The reactor is replaced by a fake one that resolves `sim::Http::get`
+///   requests after the delay in the path (plus a random jitter) has passed
+///   on the virtual clock.
+///
+/// Running the same program with the same seed always gives the same
+/// interleaving, and `explore` runs a program with many seeds to find one
+/// that fails.
+///
+/// The tasks are the same ones that `executor::spawn` hands out, so coroutines
+/// written for `Executor` can run unchanged on the `SimExecutor`, as long as
+/// they use the futures from this module instead of the ones that talk to the
+/// real reactor.
+pub struct SimExecutor {
+    seed: u64,
+    // Every fake I/O operation takes up to this much extra (virtual) time.
+    jitter: Duration,
+    // The maximum number of polls before we give up on the program.
+    max_steps: usize,
+}
+
+impl SimExecutor {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            jitter: Duration::ZERO,
+            max_steps: 1_000_000,
+        }
+    }
+
+    /// Sets the maximum random delay that's added to every fake I/O
+    /// operation. Without any jitter, requests with the same delay always
+    /// complete in the order they were made.
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the maximum number of polls the executor will make before it
+    /// considers the program stuck in a livelock.
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Runs `future` and everything it spawns to completion. Returns an
+    /// error if the program deadlocks (there are tasks left, but none are
+    /// ready and no timers are pending) or doesn't finish in `max_steps`
+    /// polls.
+    pub fn block_on<F>(&mut self, future: F) -> anyhow::Result<()>
+    where
+        F: Future<Output = String> + 'static,
+    {
+        // A previous run might have panicked half way through, so make sure
+        // we don't pick up any of its tasks.
+        reset_executor();
+        WORLD.with(|w| {
+            *w.borrow_mut() = Some(World::new(self.seed, self.jitter));
+        });
+
+        executor::spawn(future);
+        let res = self.run();
+
+        WORLD.with(|w| w.borrow_mut().take());
+        reset_executor();
+        res
+    }
+
+    fn run(&mut self) -> anyhow::Result<()> {
+        for _ in 0..self.max_steps {
+            let Some(id) = self.pick_ready() else {
+                if task_count() == 0 {
+                    return Ok(());
+                }
+                // Nothing is ready, so every task is waiting for the fake
+                // reactor. Move the virtual clock forward to the next
+                // deadline and fire the timers.
+                if !with_world(World::advance) {
+                    anyhow::bail!(
+                        "deadlock at {:?}: {} pending tasks and no timers",
+                        now(),
+                        task_count()
+                    );
+                }
+                continue;
+            };
+
+            let mut future = match executor::with_current(|e| {
+                e.tasks.borrow_mut().remove(&id)
+            }) {
+                Some(f) => f,
+                None => continue,
+            };
+            let waker = executor::waker_for(id);
+            executor::with_current(|e| e.budget.set(None));
+            if let PollState::NotReady =
+                executor::poll_task(id, &mut future, &waker)
+            {
+                executor::with_current(|e| {
+                    e.tasks.borrow_mut().insert(id, future)
+                });
+            }
+        }
+        anyhow::bail!("no progress after {} steps", self.max_steps)
+    }
+
+    /// Removes a random ID from the ready queue. This is the only scheduling
+    /// decision the simulation makes, so it's where the seed comes in.
+    fn pick_ready(&mut self) -> Option<usize> {
+        let ready_queue = executor::with_current(|e| e.ready_queue.clone());
+        let mut ready = ready_queue.lock().unwrap();
+        if ready.is_empty() {
+            return None;
+        }
+        let i = with_world(|w| w.rng.below(ready.len()));
+        Some(ready.swap_remove(i))
+    }
+}
+
+/// A seed for which the program under test failed.
+#[derive(Debug)]
+pub struct SimFailure {
+    pub seed: u64,
+    pub error: anyhow::Error,
+}
+
+/// Runs `test` once for every seed in `seeds` and returns the first seed for
+/// which it returns an error or panics. `test` is expected to build a
+/// `SimExecutor` with the seed it's given and check the outcome of the run.
+pub fn explore<F>(seeds: Range<u64>, mut test: F) -> Result<(), SimFailure>
+where
+    F: FnMut(u64) -> anyhow::Result<()>,
+{
+    for seed in seeds {
+        let error = match panic::catch_unwind(AssertUnwindSafe(|| test(seed))) {
+            Ok(Ok(())) => continue,
+            Ok(Err(e)) => e,
+            Err(payload) => {
+                let msg = payload
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+                anyhow::anyhow!("panicked: {msg}")
+            }
+        };
+        return Err(SimFailure { seed, error });
+    }
+    Ok(())
+}
+
+/// Returns the current time on the virtual clock.
+pub fn now() -> Duration {
+    with_world(|w| w.now)
+}
+
+/// Removes every task from the executor on this thread.
+fn reset_executor() {
+    executor::with_current(|e| {
+        e.tasks.borrow_mut().clear();
+        e.locals.borrow_mut().clear();
+        e.info.borrow_mut().clear();
+        e.ready_queue.lock().unwrap().clear();
+    });
+}
+
+fn task_count() -> usize {
+    executor::with_current(|e| e.tasks.borrow().len())
+}
+
+fn with_world<R>(f: impl FnOnce(&mut World) -> R) -> R {
+    WORLD.with(|w| {
+        let mut w = w.borrow_mut();
+        f(w.as_mut().expect("Called outside a simulation"))
+    })
+}
+
+// -----------------------------------------------------------------------------
+
+struct World {
+    rng: SimRng,
+    jitter: Duration,
+    now: Duration,
+    // The pending timers ordered by deadline. Timers with the same deadline
+    // are ordered by a random tie-breaker so that the order in which they
+    // fire depends on the seed and not on the order they were created in.
+    timers: BinaryHeap<Reverse<(Duration, u64, usize)>>,
+    // The most recent Waker for each timer, identified by its ID.
+    wakers: HashMap<usize, Waker>,
+    next_timer: usize,
+}
+
+impl World {
+    fn new(seed: u64, jitter: Duration) -> Self {
+        Self {
+            rng: SimRng::new(seed),
+            jitter,
+            now: Duration::ZERO,
+            timers: BinaryHeap::new(),
+            wakers: HashMap::new(),
+            next_timer: 0,
+        }
+    }
+
+    fn add_timer(&mut self, deadline: Duration) -> usize {
+        let id = self.next_timer;
+        self.next_timer += 1;
+        let tie = self.rng.next_u64();
+        self.timers.push(Reverse((deadline, tie, id)));
+        id
+    }
+
+    fn random_jitter(&mut self) -> Duration {
+        let max = self.jitter.as_millis() as usize;
+        Duration::from_millis(self.rng.below(max + 1) as u64)
+    }
+
+    /// Moves the clock to the next deadline and wakes every timer that has
+    /// expired. Returns `false` if there are no timers.
+    fn advance(&mut self) -> bool {
+        let Some(Reverse((deadline, _, _))) = self.timers.peek() else {
+            return false;
+        };
+        self.now = self.now.max(*deadline);
+        while let Some(Reverse((deadline, _, id))) = self.timers.peek() {
+            if *deadline > self.now {
+                break;
+            }
+            let id = *id;
+            self.timers.pop();
+            if let Some(waker) = self.wakers.remove(&id) {
+                waker.wake();
+            }
+        }
+        true
+    }
+}
+
+/// A small SplitMix64 generator. We only need it to be fast and reproducible.
+struct SimRng(u64);
+
+impl SimRng {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// A future that resolves once `duration` has passed on the virtual clock.
+pub struct Sleep {
+    duration: Duration,
+    // Set on the first poll, when we register the timer with the world.
+    timer: Option<(Duration, usize)>,
+}
+
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        duration,
+        timer: None,
+    }
+}
+
+impl Future for Sleep {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        with_world(|w| {
+            let (deadline, id) = *self.timer.get_or_insert_with(|| {
+                let deadline = w.now + self.duration;
+                (deadline, w.add_timer(deadline))
+            });
+            if w.now >= deadline {
+                w.wakers.remove(&id);
+                PollState::Ready(String::new())
+            } else {
+                // Always store the most recent Waker.
+                w.wakers.insert(id, waker.clone());
+                PollState::NotReady
+            }
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// A fake HTTP client that behaves like the delayserver: `/{delay}/{message}`
+/// resolves with `message` after `delay` milliseconds (plus jitter) on the
+/// virtual clock.
+pub struct Http;
+
+impl Http {
+    pub fn get(path: String) -> impl Future<Output = String> {
+        HttpGetFuture::new(&path)
+    }
+}
+
+pub struct HttpGetFuture {
+    path: String,
+    sleep: Option<Sleep>,
+}
+
+impl HttpGetFuture {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            sleep: None,
+        }
+    }
+
+    /// Splits a delayserver path into its delay and message.
+    fn parse_path(&self) -> (Duration, &str) {
+        let mut parts = self.path.trim_start_matches('/').splitn(2, '/');
+        let delay = parts.next().and_then(|d| d.parse().ok()).unwrap_or(0);
+        let message = parts.next().unwrap_or_default();
+        (Duration::from_millis(delay), message)
+    }
+}
+
+impl Future for HttpGetFuture {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        let (delay, message) = self.parse_path();
+        let message = message.to_string();
+        let sleep = self.sleep.get_or_insert_with(|| {
+            sleep(delay + with_world(World::random_jitter))
+        });
+        match sleep.poll(waker) {
+            PollState::Ready(_) => PollState::Ready(format!(
+                "HTTP/1.1 200 OK\r\n\
+                 content-length: {}\r\n\
+                 content-type: text/plain; charset=utf-8\r\n\
+                 \r\n\
+                 {message}",
+                message.len()
+            )),
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
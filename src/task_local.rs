@@ -0,0 +1,143 @@
+use std::{any::Any, collections::HashMap, marker::PhantomData, rc::Rc};
+
+use crate::executor;
+
+/// Declares one or more task-local values.
+///
+/// ```ignore
+/// task_local! {
+///     static REQUEST_ID: usize;
+/// }
+///
+/// executor::spawn_with(request(), TaskLocals::new().with(&REQUEST_ID, 7));
+///
+/// // ... and inside the task:
+/// REQUEST_ID.with(|id| println!("request {id}"));
+/// ```
+///
+/// Each value belongs to the task it was set on, the same way a
+/// `thread_local!` belongs to a thread. Since our tasks never leave the
+/// thread they were spawned on, the values don't need to be `Send`.
+#[macro_export]
+macro_rules! task_local {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty;)+) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::task_local::LocalKey<$t> =
+                $crate::task_local::LocalKey::new(stringify!($name));
+        )+
+    };
+}
+
+/// A key for a task-local value, created with the `task_local!` macro.
+///
+/// The key itself holds no data. The address of the `static` identifies the
+/// value in the `TaskLocals` of each task.
+pub struct LocalKey<T: 'static> {
+    name: &'static str,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: 'static> LocalKey<T> {
+    #[doc(hidden)]
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Calls `f` with a reference to the value for the current task.
+    ///
+    /// Panics if it's called outside a task or if the current task doesn't
+    /// have a value for this key.
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        self.try_with(f).unwrap_or_else(|| {
+            panic!("task-local `{}` is not set for this task", self.name)
+        })
+    }
+
+    /// Like `with`, but returns `None` instead of panicking when there's no
+    /// value.
+    pub fn try_with<R>(&'static self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        // We clone the `Rc` out of the executor before we call `f`, so that
+        // `f` is free to spawn tasks or set other task-local values.
+        let value = executor::with_current(|e| {
+            let id = e.current_task.get()?;
+            e.locals.borrow().get(&id)?.get(self)
+        })?;
+        let value =
+            value.downcast_ref::<T>().expect("task-local type mismatch");
+        Some(f(value))
+    }
+
+    /// Sets the value for the current task, replacing any value it had.
+    ///
+    /// Panics if it's called outside a task.
+    pub fn set(&'static self, value: T) {
+        executor::with_current(|e| {
+            let id = e.current_task.get().expect("Called outside a task");
+            e.locals
+                .borrow_mut()
+                .entry(id)
+                .or_default()
+                .values
+                .insert(self.key(), Rc::new(value));
+        });
+    }
+
+    fn key(&'static self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// The task-local values of a single task.
+///
+/// The values are reference counted, so passing them on to a child task with
+/// `inherit` is cheap, and the parent and the child see the same value.
+#[derive(Clone, Default)]
+pub struct TaskLocals {
+    values: HashMap<usize, Rc<dyn Any>>,
+}
+
+impl TaskLocals {
+    /// Creates an empty set of task-local values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of the task-local values of the task that's currently
+    /// being polled, so that a child task can inherit them. Returns an empty
+    /// set when called outside a task.
+    pub fn inherit() -> Self {
+        executor::with_current(|e| {
+            e.current_task
+                .get()
+                .and_then(|id| e.locals.borrow().get(&id).cloned())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Sets the value for `key`, replacing any value it had.
+    pub fn with<T: 'static>(
+        mut self,
+        key: &'static LocalKey<T>,
+        value: T,
+    ) -> Self {
+        self.values.insert(key.key(), Rc::new(value));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn get<T: 'static>(
+        &self,
+        key: &'static LocalKey<T>,
+    ) -> Option<Rc<dyn Any>> {
+        self.values.get(&key.key()).cloned()
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    executor::Waker,
+    future_with_waker::{Future, OrErrorMessage, PollState},
+    reactor::{Reactor, reactor},
+};
+
+/// Returns a future that completes once `duration` has passed.
+///
+/// The output is an empty `String`. If the reactor is shut down before the
+/// deadline, the future completes early, with a description of the error
+/// instead (see `future_with_waker::OrErrorMessage`). Use `try_sleep` to tell
+/// the two apart.
+pub fn sleep(duration: Duration) -> impl Future<Output = String> {
+    sleep_until(Instant::now() + duration)
+}
+
+/// Returns a future that completes once `deadline` has passed. See `sleep`.
+pub fn sleep_until(deadline: Instant) -> impl Future<Output = String> {
+    OrErrorMessage::new(try_sleep_until(deadline), "Sleeping", |()| {
+        String::new()
+    })
+}
+
+/// Returns a future that completes once `duration` has passed, or fails if
+/// the reactor is shut down before that.
+pub fn try_sleep(duration: Duration) -> Sleep {
+    try_sleep_until(Instant::now() + duration)
+}
+
+/// Returns a future that completes once `deadline` has passed, or fails if
+/// the reactor is shut down before that.
+pub fn try_sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        timer: None,
+    }
+}
+
+/// Returns an `Interval` that ticks every `period`, starting now.
+///
+/// Panics if `period` is zero.
+pub fn interval(period: Duration) -> Interval {
+    assert!(period > Duration::ZERO, "`period` must be non-zero");
+    Interval {
+        period,
+        sleep: try_sleep_until(Instant::now()),
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// A future that completes at a given point in time. See `try_sleep`.
+pub struct Sleep {
+    deadline: Instant,
+    // The reactor our timer is registered with, and its ID. Both are set on
+    // the first poll that doesn't find the deadline has passed already, and
+    // kept from then on, even after the reactor shuts down.
+    timer: Option<(Arc<Reactor>, usize)>,
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Moves the deadline. If the timer is already registered, the next poll
+    /// updates it.
+    pub fn reset(&mut self, deadline: Instant) {
+        self.deadline = deadline;
+    }
+
+    fn cancel(&self) {
+        if let Some((reactor, id)) = &self.timer {
+            reactor.cancel_timer(*id);
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        // There's no need for a reactor if we're done before we start.
+        if self.timer.is_none() && Instant::now() >= self.deadline {
+            return PollState::Ready(Ok(()));
+        }
+
+        let (reactor, id) = self.timer.get_or_insert_with(|| {
+            let reactor = reactor();
+            let id = reactor.next_id();
+            (reactor, id)
+        });
+        // Once the reactor is shut down, nobody is going to wake us. We keep
+        // hold of it, so that every later poll fails here as well instead of
+        // looking for a reactor again.
+        if let Err(e) = reactor.check_shutdown() {
+            return PollState::Ready(Err(e));
+        }
+        if Instant::now() >= self.deadline {
+            self.cancel();
+            return PollState::Ready(Ok(()));
+        }
+        // Like with I/O, we register the Waker from the most recent call
+        // every time we're polled.
+        reactor.set_timer(*id, self.deadline, waker);
+        PollState::NotReady
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// Ticks at a fixed rate. See `interval`.
+///
+/// If a tick is missed because the task wasn't polled in time, the interval
+/// ticks straight away and carries on a full `period` after that, rather
+/// than firing all the missed ticks in a burst.
+///
+/// Like `Sleep`, it stops waiting once the reactor it started on is shut
+/// down, so from then on it ticks every time it's polled, and doesn't move
+/// to a reactor that's started after that.
+pub struct Interval {
+    period: Duration,
+    sleep: Sleep,
+}
+
+impl Interval {
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Polls for the next tick, returning the time it was scheduled for.
+    pub fn poll_tick(&mut self, waker: &Waker) -> PollState<Instant> {
+        match self.sleep.poll(waker) {
+            PollState::Ready(_) => {
+                let tick = self.sleep.deadline();
+                let now = Instant::now();
+                let mut next = tick + self.period;
+                if next <= now {
+                    next = now + self.period;
+                }
+                self.sleep.reset(next);
+                PollState::Ready(tick)
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+
+    /// Returns a future that completes on the next tick.
+    pub fn tick(&mut self) -> Tick<'_> {
+        Tick { interval: self }
+    }
+}
+
+/// The future returned by `Interval::tick`.
+pub struct Tick<'a> {
+    interval: &'a mut Interval,
+}
+
+impl Future for Tick<'_> {
+    type Output = Instant;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        self.interval.poll_tick(waker)
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    cell::Cell,
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::Instant,
+};
+
+// Recording is off until someone calls `enable`, so that the executor and
+// the reactor only pay for an atomic load per event when nobody is looking.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+// The point in time that all the timestamps in the trace are relative to.
+static START: OnceLock<Instant> = OnceLock::new();
+
+// All the events recorded so far, from every thread.
+static EVENTS: Mutex<Vec<TraceEvent>> = Mutex::new(Vec::new());
+
+// Hands out a small number for each thread that records an event. Chrome
+// traces identify threads by number, and the thread's name is written to the
+// trace as metadata the first time we see it.
+static NEXT_TID: AtomicUsize = AtomicUsize::new(1);
+
+// The names of the threads that have recorded events, by number.
+static THREAD_NAMES: Mutex<Vec<(usize, String)>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static TID: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The things that happen to a task or an I/O source during its lifetime.
+#[derive(Debug, Clone, Copy)]
+pub enum EventKind {
+    /// A task was spawned onto the executor.
+    Spawn,
+    /// The executor started polling a task.
+    PollStart,
+    /// The executor finished polling a task.
+    PollEnd { ready: bool },
+    /// A task's Waker was called.
+    Wake,
+    /// The executor parked its thread because there was nothing to do.
+    Park,
+    /// The executor's thread was unparked.
+    Unpark,
+    /// A source was registered with the reactor.
+    Register,
+    /// A source was deregistered from the reactor.
+    Deregister,
+    /// The reactor received an event for a source.
+    Readiness { readable: bool, writable: bool },
+}
+
+struct TraceEvent {
+    kind: EventKind,
+    // The ID of the task or the reactor ID of the source.
+    id: usize,
+    // Microseconds since `START`.
+    ts: u128,
+    tid: usize,
+}
+
+/// Starts recording task lifecycle events.
+pub fn enable() {
+    START.get_or_init(Instant::now);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Stops recording task lifecycle events. The events recorded so far are
+/// kept until they're written out with `write`.
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Records an event for the task or source identified by `id` on the
+/// current thread.
+pub fn record(kind: EventKind, id: usize) {
+    if !is_enabled() {
+        return;
+    }
+    let ts = START.get_or_init(Instant::now).elapsed().as_micros();
+    let tid = current_tid();
+    EVENTS
+        .lock()
+        .map(|mut e| e.push(TraceEvent { kind, id, ts, tid }))
+        .unwrap();
+}
+
+/// Writes every event recorded so far to `path` in the Chrome trace event
+/// format, and clears them. The file can be opened with `chrome://tracing`
+/// or in Perfetto (https://ui.perfetto.dev).
+///
+/// Polls show up as slices on the executor's thread, and so does the time an
+/// executor spends parked. Everything else is an instant event.
+pub fn write(path: impl AsRef<Path>) -> io::Result<()> {
+    let events = EVENTS.lock().map(|mut e| std::mem::take(&mut *e)).unwrap();
+    let mut out = BufWriter::new(File::create(path)?);
+
+    writeln!(out, "{{\"traceEvents\": [")?;
+    let mut first = true;
+    for line in thread_names().into_iter().chain(events.iter().map(to_json)) {
+        if !first {
+            writeln!(out, ",")?;
+        }
+        first = false;
+        write!(out, "  {line}")?;
+    }
+    writeln!(out, "\n]}}")?;
+    out.flush()
+}
+
+/// Returns the number that identifies the current thread in the trace, and
+/// remembers its name the first time we see it.
+fn current_tid() -> usize {
+    TID.with(|tid| {
+        if tid.get() == 0 {
+            let id = NEXT_TID.fetch_add(1, Ordering::Relaxed);
+            let name = std::thread::current()
+                .name()
+                .unwrap_or("unnamed")
+                .to_string();
+            THREAD_NAMES.lock().unwrap().push((id, name));
+            tid.set(id);
+        }
+        tid.get()
+    })
+}
+
+fn thread_names() -> Vec<String> {
+    THREAD_NAMES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(tid, name)| {
+            format!(
+                "{{\"name\": \"thread_name\", \"ph\": \"M\", \"pid\": 1, \
+                 \"tid\": {tid}, \"args\": {{\"name\": \"{}\"}}}}",
+                escape(name)
+            )
+        })
+        .collect()
+}
+
+fn to_json(e: &TraceEvent) -> String {
+    let TraceEvent { kind, id, ts, tid } = e;
+    let (name, ph, cat, mut args) = match kind {
+        EventKind::Spawn => (format!("spawn {id}"), "i", "task", String::new()),
+        EventKind::PollStart => {
+            (format!("poll {id}"), "B", "task", String::new())
+        }
+        EventKind::PollEnd { ready } => (
+            format!("poll {id}"),
+            "E",
+            "task",
+            format!(", \"ready\": {ready}"),
+        ),
+        EventKind::Wake => (format!("wake {id}"), "i", "task", String::new()),
+        EventKind::Park => ("park".to_string(), "B", "executor", String::new()),
+        EventKind::Unpark => {
+            ("park".to_string(), "E", "executor", String::new())
+        }
+        EventKind::Register => {
+            (format!("register {id}"), "i", "reactor", String::new())
+        }
+        EventKind::Deregister => {
+            (format!("deregister {id}"), "i", "reactor", String::new())
+        }
+        EventKind::Readiness { readable, writable } => (
+            format!("ready {id}"),
+            "i",
+            "reactor",
+            format!(", \"readable\": {readable}, \"writable\": {writable}"),
+        ),
+    };
+
+    // Instant events are scoped to the thread they happened on.
+    let scope = if ph == "i" { ", \"s\": \"t\"" } else { "" };
+    args.insert_str(0, &format!("\"id\": {id}"));
+
+    format!(
+        "{{\"name\": \"{name}\", \"cat\": \"{cat}\", \"ph\": \"{ph}\", \
+         \"ts\": {ts}, \"pid\": 1, \"tid\": {tid}{scope}, \
+         \"args\": {{{args}}}}}"
+    )
+}
+
+/// Escapes a string so that it can be put inside a JSON string literal.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => {
+                write!(out, "\\u{:04x}", c as u32).unwrap();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
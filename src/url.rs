@@ -0,0 +1,204 @@
+//! The URLs the HTTP clients accept.
+//!
+//! Either a full `http://host[:port]/path` URL, where the host can be a name,
+//! an IPv4 address or an IPv6 address in brackets (`http://[::1]:8080/`), or
+//! just a path like `/600/HelloWorld`, which goes to the delayserver.
+use std::{
+    error::Error,
+    fmt,
+    io,
+    net::{IpAddr, Ipv6Addr, SocketAddr, ToSocketAddrs},
+    str::FromStr,
+};
+
+/// Where a bare path is sent: the delayserver.
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 7070;
+
+/// The port of an `http://` URL that doesn't give one.
+const HTTP_PORT: u16 = 80;
+
+/// A parsed URL. See the module docs for what's accepted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Url {
+    // Without the brackets around an IPv6 address.
+    host: String,
+    port: u16,
+    // The path and the query, which is what goes into the request line.
+    // Always starts with a `/`.
+    path: String,
+}
+
+impl Url {
+    pub fn parse(s: &str) -> Result<Self, UrlError> {
+        // The fragment is only for the client, and never sent to the server.
+        let s = s.split_once('#').map_or(s, |(s, _)| s);
+
+        if s.starts_with('/') {
+            return Ok(Self {
+                host: DEFAULT_HOST.to_string(),
+                port: DEFAULT_PORT,
+                path: s.to_string(),
+            });
+        }
+
+        let (scheme, rest) = s.split_once("://").ok_or(UrlError::NotAUrl)?;
+        if !scheme.eq_ignore_ascii_case("http") {
+            return Err(UrlError::UnsupportedScheme(scheme.to_string()));
+        }
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+        let path = match path {
+            "" => "/".to_string(),
+            query if query.starts_with('?') => format!("/{query}"),
+            path => path.to_string(),
+        };
+        if authority.contains('@') {
+            return Err(UrlError::InvalidHost(authority.to_string()));
+        }
+
+        let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+            // An IPv6 address, which has colons of its own.
+            let (host, port) = rest
+                .split_once(']')
+                .ok_or_else(|| UrlError::InvalidHost(authority.to_string()))?;
+            if host.parse::<Ipv6Addr>().is_err() {
+                return Err(UrlError::InvalidHost(host.to_string()));
+            }
+            match port {
+                "" => (host, None),
+                port => match port.strip_prefix(':') {
+                    Some(port) => (host, Some(port)),
+                    None => {
+                        return Err(UrlError::InvalidHost(
+                            authority.to_string(),
+                        ));
+                    }
+                },
+            }
+        } else {
+            match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            }
+        };
+
+        if host.is_empty() {
+            return Err(UrlError::MissingHost);
+        }
+        let valid = |c: char| c.is_ascii_alphanumeric() || "-._:".contains(c);
+        if !host.chars().all(valid) {
+            return Err(UrlError::InvalidHost(host.to_string()));
+        }
+        let port = match port {
+            None | Some("") => HTTP_PORT,
+            Some(port) => port
+                .parse()
+                .map_err(|_| UrlError::InvalidPort(port.to_string()))?,
+        };
+
+        Ok(Self {
+            host: host.to_ascii_lowercase(),
+            port,
+            path,
+        })
+    }
+
+    /// The host, without brackets if it's an IPv6 address.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The path and the query.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The value of the `Host` header of a request for this URL. The port is
+    /// left out if it's the default one.
+    pub fn host_header(&self) -> String {
+        let host = match self.host.contains(':') {
+            true => format!("[{}]", self.host),
+            false => self.host.clone(),
+        };
+        match self.port {
+            HTTP_PORT => host,
+            port => format!("{host}:{port}"),
+        }
+    }
+
+    /// The address to connect to, if the host is an IP address. If it's a
+    /// name, it has to be resolved first (see `resolve`).
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        let ip = self.host.parse::<IpAddr>().ok()?;
+        Some(SocketAddr::new(ip, self.port))
+    }
+
+    /// Looks up the addresses of the host. This blocks, so don't call it on
+    /// the executor thread.
+    pub fn resolve(&self) -> io::Result<Vec<SocketAddr>> {
+        resolve(&self.host, self.port)
+    }
+}
+
+/// Looks up the addresses of `host`. This blocks while it asks the system's
+/// resolver.
+pub fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    let addrs: Vec<_> = (host, port).to_socket_addrs()?.collect();
+    if addrs.is_empty() {
+        let msg = format!("no addresses found for {host}");
+        return Err(io::Error::new(io::ErrorKind::NotFound, msg));
+    }
+    Ok(addrs)
+}
+
+impl FromStr for Url {
+    type Err = UrlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}{}", self.host_header(), self.path)
+    }
+}
+
+// -----------------------------------------------------------------------------
+
+/// Why a URL couldn't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrlError {
+    /// Neither a path nor a URL with a scheme.
+    NotAUrl,
+    UnsupportedScheme(String),
+    MissingHost,
+    InvalidHost(String),
+    InvalidPort(String),
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UrlError::NotAUrl => {
+                write!(f, "expected a path or an `http://` URL")
+            }
+            UrlError::UnsupportedScheme(scheme) => {
+                write!(f, "unsupported scheme `{scheme}`")
+            }
+            UrlError::MissingHost => write!(f, "missing host"),
+            UrlError::InvalidHost(host) => write!(f, "invalid host `{host}`"),
+            UrlError::InvalidPort(port) => write!(f, "invalid port `{port}`"),
+        }
+    }
+}
+
+impl Error for UrlError {}